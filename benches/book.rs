@@ -0,0 +1,136 @@
+mod support;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use match_engine::{
+    config::TradingRules,
+    engine::MatchEngine,
+    model::{
+        Command, IncomingOrder, OrderId, OrderSide, OrderType, Price, SelfTradeAction, TimeInForce,
+    },
+};
+use uuid::Uuid;
+
+use support::{SYMBOL, submit};
+
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+fn engine(self_trade_action: SelfTradeAction) -> MatchEngine {
+    support::engine(self_trade_action, TradingRules::default())
+}
+
+fn limit(order_id: OrderId, user_id: Uuid, side: OrderSide, price: i64, amount: u64) -> Command {
+    Command::NewOrder(IncomingOrder {
+        symbol: SYMBOL.to_string(),
+        order_id,
+        user_id,
+        side,
+        amount,
+        order_type: OrderType::Limit {
+            post_only: false,
+            price: Price::from(price),
+            tif: TimeInForce::GTC,
+            display_amount: None,
+            min_qty: None,
+            all_or_none: false,
+        },
+        self_trade_action: None,
+    })
+}
+
+fn cancel(order_id: OrderId, user_id: Uuid) -> Command {
+    Command::CancelOrder {
+        symbol: SYMBOL.to_string(),
+        order_id,
+        user_id,
+    }
+}
+
+// A buyer whose own asks sit at the top of the book sweeps ten single-lot
+// asks queued behind them, skipping its own orders under self-trade
+// prevention. The asks it takes are replaced so every iteration sees the same
+// book.
+fn stp_skip(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stp_skip");
+    for size in SIZES {
+        let owner = Uuid::from_u128(1);
+        let other = Uuid::from_u128(2);
+        let mut engine = engine(SelfTradeAction::Skip);
+        for order_id in 0..size as OrderId {
+            submit(&mut engine, limit(order_id, owner, OrderSide::Sell, 100, 1));
+        }
+        let mut order_id = size as OrderId;
+        for _ in 0..10 {
+            submit(&mut engine, limit(order_id, other, OrderSide::Sell, 101, 1));
+            order_id += 1;
+        }
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                order_id += 1;
+                submit(&mut engine, limit(order_id, owner, OrderSide::Buy, 101, 10));
+                for _ in 0..10 {
+                    order_id += 1;
+                    submit(&mut engine, limit(order_id, other, OrderSide::Sell, 101, 1));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+// Cancels the order in the middle of a single deep price level and queues a
+// replacement at the back of it.
+fn cancel_replace(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cancel_replace");
+    for size in SIZES {
+        let user_id = Uuid::from_u128(1);
+        let mut engine = engine(SelfTradeAction::default());
+        for order_id in 0..size as OrderId {
+            submit(
+                &mut engine,
+                limit(order_id, user_id, OrderSide::Buy, 100, 1),
+            );
+        }
+        let mut oldest = size as OrderId / 2;
+        let mut order_id = size as OrderId;
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                submit(&mut engine, cancel(oldest, user_id));
+                submit(
+                    &mut engine,
+                    limit(order_id, user_id, OrderSide::Buy, 100, 1),
+                );
+                oldest += 1;
+                order_id += 1;
+            })
+        });
+    }
+    group.finish();
+}
+
+// Partially fills the maker at the front of a deep level, which stays in
+// place at the head of its queue.
+fn partial_fill(c: &mut Criterion) {
+    let mut group = c.benchmark_group("partial_fill");
+    for size in SIZES {
+        let maker = Uuid::from_u128(1);
+        let taker = Uuid::from_u128(2);
+        let mut engine = engine(SelfTradeAction::default());
+        for order_id in 0..size as OrderId {
+            submit(
+                &mut engine,
+                limit(order_id, maker, OrderSide::Sell, 100, 1_000_000_000_000),
+            );
+        }
+        let mut order_id = size as OrderId;
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                order_id += 1;
+                submit(&mut engine, limit(order_id, taker, OrderSide::Buy, 100, 1));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, stp_skip, cancel_replace, partial_fill);
+criterion_main!(benches);
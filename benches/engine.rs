@@ -0,0 +1,43 @@
+mod support;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use match_engine::{config::TradingRules, model::SelfTradeAction};
+
+use support::{
+    engine,
+    flow::{OrderFlow, scenarios},
+    submit,
+};
+
+const COMMANDS: usize = 10_000;
+
+// Throughput of `MatchEngine::process` over a fresh book per iteration.
+fn process(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process");
+    group.throughput(Throughput::Elements(COMMANDS as u64));
+    for (name, config) in scenarios() {
+        let commands: Vec<_> = OrderFlow::new(config)
+            .take(COMMANDS)
+            .into_iter()
+            .map(|(_, command)| command)
+            .collect();
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter_batched(
+                || {
+                    let engine = engine(SelfTradeAction::CancelTaker, TradingRules::default());
+                    (engine, commands.clone())
+                },
+                |(mut engine, commands)| {
+                    for command in commands {
+                        submit(&mut engine, command);
+                    }
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, process);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+mod support;
+
+use std::{hint::black_box, time::Instant};
+
+use hdrhistogram::Histogram;
+use match_engine::{config::TradingRules, model::SelfTradeAction};
+
+use support::{
+    engine,
+    flow::{Operation, OrderFlow, scenarios},
+    submit,
+};
+
+const WARMUP: usize = 50_000;
+const SAMPLES: usize = 500_000;
+
+// Per-operation latency of `MatchEngine::process`, in nanoseconds, for each
+// flow scenario. The book is warmed up first so orders meet resting depth.
+// Only `process` itself is timed; draining the book feeds is not.
+fn main() {
+    for (name, config) in scenarios() {
+        let mut engine = engine(SelfTradeAction::CancelTaker, TradingRules::default());
+        let mut flow = OrderFlow::new(config);
+        for (_, command) in flow.take(WARMUP) {
+            submit(&mut engine, command);
+        }
+
+        let mut histograms: Vec<Histogram<u64>> = Operation::ALL
+            .iter()
+            .map(|_| Histogram::new_with_bounds(1, 60_000_000_000, 3).unwrap())
+            .collect();
+        for (operation, command) in flow.take(SAMPLES) {
+            let started = Instant::now();
+            let events = engine.process(command, 0, 0);
+            let elapsed = started.elapsed().as_nanos() as u64;
+            black_box(events);
+            black_box(engine.order_book_updates());
+            black_box(engine.depth_updates());
+            let index = Operation::ALL
+                .iter()
+                .position(|op| *op == operation)
+                .unwrap();
+            histograms[index].saturating_record(elapsed.max(1));
+        }
+
+        println!("{} ({} commands after {} warm-up)", name, SAMPLES, WARMUP);
+        println!(
+            "  {:<8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10}",
+            "op", "count", "p50", "p90", "p99", "p99.9", "max"
+        );
+        for (operation, histogram) in Operation::ALL.iter().zip(&histograms) {
+            if histogram.is_empty() {
+                continue;
+            }
+            println!(
+                "  {:<8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10}",
+                format!("{:?}", operation),
+                histogram.len(),
+                histogram.value_at_quantile(0.5),
+                histogram.value_at_quantile(0.9),
+                histogram.value_at_quantile(0.99),
+                histogram.value_at_quantile(0.999),
+                histogram.max()
+            );
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use match_engine::model::{
+    Amount, Command, IncomingOrder, OrderId, OrderSide, OrderType, PRICE_SCALE, Price, TimeInForce,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use uuid::Uuid;
+
+use super::SYMBOL;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Limit,
+    Market,
+    Ioc,
+    Fok,
+    Cancel,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 5] = [
+        Operation::Limit,
+        Operation::Market,
+        Operation::Ioc,
+        Operation::Fok,
+        Operation::Cancel,
+    ];
+}
+
+// Relative weights of the new-order types; only their ratios matter.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderMix {
+    pub limit: u32,
+    pub market: u32,
+    pub ioc: u32,
+    pub fok: u32,
+}
+
+// Prices are drawn in ticks around `mid_ticks`, from a triangular
+// distribution `spread_ticks` wide on either side. Buys and sells share it,
+// so roughly half of all limit orders cross the spread on arrival.
+#[derive(Debug, Clone, Copy)]
+pub struct FlowConfig {
+    pub seed: u64,
+    pub users: u64,
+    pub mix: OrderMix,
+    // Share of commands that cancel a previously submitted GTC order.
+    pub cancel_ratio: f64,
+    // Share of orders sent by the user who last quoted the opposite side.
+    pub self_trade_ratio: f64,
+    pub mid_ticks: i64,
+    pub tick_size: Price,
+    pub spread_ticks: i64,
+    pub max_amount: Amount,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        FlowConfig {
+            seed: 7,
+            users: 100,
+            mix: OrderMix {
+                limit: 80,
+                market: 5,
+                ioc: 10,
+                fok: 5,
+            },
+            cancel_ratio: 0.3,
+            self_trade_ratio: 0.05,
+            mid_ticks: 10_000,
+            tick_size: Price::from_units(PRICE_SCALE / 100),
+            spread_ticks: 50,
+            max_amount: 100,
+        }
+    }
+}
+
+// Named order-flow profiles, all seeded so every run replays the same commands.
+pub fn scenarios() -> Vec<(&'static str, FlowConfig)> {
+    let balanced = FlowConfig::default();
+    vec![
+        ("balanced", balanced),
+        (
+            "aggressive",
+            FlowConfig {
+                mix: OrderMix {
+                    limit: 40,
+                    market: 30,
+                    ioc: 20,
+                    fok: 10,
+                },
+                cancel_ratio: 0.1,
+                ..balanced
+            },
+        ),
+        (
+            "cancel_heavy",
+            FlowConfig {
+                cancel_ratio: 0.7,
+                spread_ticks: 200,
+                ..balanced
+            },
+        ),
+        (
+            "self_trade",
+            FlowConfig {
+                users: 5,
+                self_trade_ratio: 0.5,
+                ..balanced
+            },
+        ),
+    ]
+}
+
+// Deterministic stream of commands for one symbol: the same config always
+// yields the same commands, independent of how the engine responds to them.
+pub struct OrderFlow {
+    config: FlowConfig,
+    rng: StdRng,
+    next_order_id: OrderId,
+    live_orders: Vec<(OrderId, Uuid)>,
+    last_quoted_by: [Option<Uuid>; 2],
+}
+
+impl OrderFlow {
+    pub fn new(config: FlowConfig) -> Self {
+        OrderFlow {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            next_order_id: 0,
+            live_orders: Vec::new(),
+            last_quoted_by: [None, None],
+        }
+    }
+
+    pub fn take(&mut self, count: usize) -> Vec<(Operation, Command)> {
+        (0..count).map(|_| self.next_command()).collect()
+    }
+
+    pub fn next_command(&mut self) -> (Operation, Command) {
+        if !self.live_orders.is_empty() && self.rng.random_bool(self.config.cancel_ratio) {
+            let index = self.rng.random_range(0..self.live_orders.len());
+            let (order_id, user_id) = self.live_orders.swap_remove(index);
+            let command = Command::CancelOrder {
+                symbol: SYMBOL.to_string(),
+                order_id,
+                user_id,
+            };
+            return (Operation::Cancel, command);
+        }
+
+        let side = if self.rng.random_bool(0.5) {
+            OrderSide::Buy
+        } else {
+            OrderSide::Sell
+        };
+        let user_id = match self.last_quoted_by[1 - Self::side_index(side)] {
+            Some(user_id) if self.rng.random_bool(self.config.self_trade_ratio) => user_id,
+            _ => Uuid::from_u128(self.rng.random_range(0..self.config.users) as u128 + 1),
+        };
+        let order_id = self.next_order_id;
+        self.next_order_id += 1;
+
+        let operation = self.pick_operation();
+        let order_type = match operation {
+            Operation::Market => OrderType::Market {
+                tif: TimeInForce::IOC,
+                market_to_limit: false,
+            },
+            Operation::Limit | Operation::Ioc | Operation::Fok => OrderType::Limit {
+                post_only: false,
+                price: self.price(),
+                tif: match operation {
+                    Operation::Ioc => TimeInForce::IOC,
+                    Operation::Fok => TimeInForce::FOK,
+                    _ => TimeInForce::GTC,
+                },
+                display_amount: None,
+                min_qty: None,
+                all_or_none: false,
+            },
+            Operation::Cancel => unreachable!(),
+        };
+        if operation == Operation::Limit {
+            self.live_orders.push((order_id, user_id));
+            self.last_quoted_by[Self::side_index(side)] = Some(user_id);
+        }
+
+        let command = Command::NewOrder(IncomingOrder {
+            symbol: SYMBOL.to_string(),
+            order_id,
+            user_id,
+            side,
+            amount: self.rng.random_range(1..=self.config.max_amount),
+            order_type,
+            self_trade_action: None,
+        });
+        (operation, command)
+    }
+
+    fn pick_operation(&mut self) -> Operation {
+        let OrderMix {
+            limit,
+            market,
+            ioc,
+            fok,
+        } = self.config.mix;
+        let roll = self.rng.random_range(0..limit + market + ioc + fok);
+        if roll < limit {
+            Operation::Limit
+        } else if roll < limit + market {
+            Operation::Market
+        } else if roll < limit + market + ioc {
+            Operation::Ioc
+        } else {
+            Operation::Fok
+        }
+    }
+
+    fn price(&mut self) -> Price {
+        let spread = self.config.spread_ticks;
+        let offset =
+            self.rng.random_range(-spread..=spread) + self.rng.random_range(-spread..=spread);
+        let ticks = (self.config.mid_ticks + offset / 2).max(1);
+        Price::from_units(ticks * self.config.tick_size.units())
+    }
+
+    fn side_index(side: OrderSide) -> usize {
+        match side {
+            OrderSide::Buy => 0,
+            OrderSide::Sell => 1,
+        }
+    }
+}
@@ -0,0 +1,28 @@
+#![allow(dead_code)]
+pub mod flow;
+
+use std::hint::black_box;
+
+use match_engine::{
+    config::{InstrumentConfig, TradingRules},
+    engine::MatchEngine,
+    model::{Command, SelfTradeAction},
+};
+
+pub const SYMBOL: &str = "BENCH";
+
+pub fn engine(self_trade_action: SelfTradeAction, rules: TradingRules) -> MatchEngine {
+    MatchEngine::new(&InstrumentConfig {
+        symbol: SYMBOL.to_string(),
+        self_trade_action,
+        rules,
+    })
+}
+
+// Processes a command the way the service does, draining the book feeds the
+// engine buffers between commands.
+pub fn submit(engine: &mut MatchEngine, command: Command) {
+    black_box(engine.process(command, 0, 0));
+    black_box(engine.order_book_updates());
+    black_box(engine.depth_updates());
+}
@@ -0,0 +1,157 @@
+use anyhow::{Context, Result, bail};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use serde::{Deserialize, Deserializer, de};
+
+use crate::model::{
+    Amount, PRICE_DECIMALS, PRICE_SCALE, Price, SelfTradeAction, Symbol, TickSize, Timestamp,
+    TradingStatus,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InstrumentConfig {
+    pub symbol: Symbol,
+    #[serde(default)]
+    pub self_trade_action: SelfTradeAction,
+    #[serde(flatten)]
+    pub rules: TradingRules,
+}
+
+// Every rule is optional; an instrument without any only rejects zero
+// amounts and non-positive prices. Bands are fractions of the reference price,
+// which is the last trade or `reference_price` before the first one.
+// `market_collar` defaults to the price band.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TradingRules {
+    pub tick_size: Option<Price>,
+    pub lot_size: Option<Amount>,
+    pub min_amount: Option<Amount>,
+    pub max_amount: Option<Amount>,
+    pub min_notional: Option<Price>,
+    pub reference_price: Option<Price>,
+    pub price_band: Option<Fraction>,
+    pub market_collar: Option<Fraction>,
+    pub band_action: BandAction,
+    pub circuit_breaker: Option<CircuitBreaker>,
+    pub schedule: Vec<ScheduledTransition>,
+}
+
+// A fraction of a price, like a band or breaker threshold, written as a
+// decimal and held in the same fixed-point units as `Price`, so the checks
+// using it on every order stay in integer arithmetic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fraction(i64);
+
+impl Fraction {
+    pub const ZERO: Fraction = Fraction(0);
+    pub const ONE: Fraction = Fraction(PRICE_SCALE);
+
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for Fraction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = <Decimal as Deserialize>::deserialize(deserializer)?;
+        value
+            .checked_mul(Decimal::from(PRICE_SCALE))
+            .filter(|units| units.fract().is_zero())
+            .and_then(|units| units.to_i64())
+            .map(Fraction)
+            .ok_or_else(|| {
+                de::Error::custom(format!(
+                    "fraction {} has more than {} decimals or is out of range",
+                    value, PRICE_DECIMALS
+                ))
+            })
+    }
+}
+
+// Daily session schedule; each entry switches the instrument to `status` at
+// `at` UTC, timed by the ingress time of incoming commands.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduledTransition {
+    pub at: TimeOfDay,
+    pub status: TradingStatus,
+}
+
+// Written as "HH:MM" or "HH:MM:SS"; held as nanoseconds since midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(Timestamp);
+
+impl TimeOfDay {
+    pub fn nanos(&self) -> Timestamp {
+        self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        let parts: Vec<&str> = raw.split(':').collect();
+        let fields: Option<Vec<i64>> = parts.iter().map(|part| part.parse().ok()).collect();
+        let seconds = match fields.as_deref() {
+            Some([hours, minutes]) if *hours < 24 && *minutes < 60 => hours * 3_600 + minutes * 60,
+            Some([hours, minutes, seconds]) if *hours < 24 && *minutes < 60 && *seconds < 60 => {
+                hours * 3_600 + minutes * 60 + seconds
+            }
+            _ => return Err(de::Error::custom(format!("invalid time of day {}", raw))),
+        };
+        Ok(TimeOfDay(seconds * 1_000_000_000))
+    }
+}
+
+// Halts the instrument once the highest and lowest trade prices seen within
+// `window_ms` of event time are more than `threshold` apart, relative to the
+// lowest. Trading stays halted until a `Resume` command.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CircuitBreaker {
+    pub threshold: Fraction,
+    pub window_ms: i64,
+}
+
+// What happens to a limit order priced outside the band: rejected outright,
+// or matched up to the band edge with the remainder cancelled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum BandAction {
+    Reject,
+    #[default]
+    CancelRemainder,
+}
+
+pub fn load_instruments(path: &str) -> Result<Vec<InstrumentConfig>> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read instruments config {}", path))?;
+    let mut instruments: Vec<InstrumentConfig> = serde_json::from_str(&raw)
+        .with_context(|| format!("Failed to parse instruments config {}", path))?;
+    for instrument in &mut instruments {
+        instrument
+            .rules
+            .schedule
+            .sort_by_key(|transition| transition.at);
+        let rules = &instrument.rules;
+        if rules
+            .tick_size
+            .is_some_and(|tick_size| tick_size <= Price::ZERO)
+            || rules
+                .reference_price
+                .is_some_and(|price| TickSize::new(rules.tick_size).to_ticks(price).is_none())
+            || rules.lot_size == Some(0)
+            || rules
+                .price_band
+                .into_iter()
+                .chain(rules.market_collar)
+                .any(|band| band < Fraction::ZERO || band >= Fraction::ONE)
+            || rules.circuit_breaker.as_ref().is_some_and(|breaker| {
+                breaker.threshold <= Fraction::ZERO || breaker.window_ms <= 0
+            })
+        {
+            bail!(
+                "Instrument {} has an invalid tick size, reference price, lot size, band or circuit breaker",
+                instrument.symbol
+            );
+        }
+    }
+    Ok(instruments)
+}
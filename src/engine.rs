@@ -1,97 +1,900 @@
-use std::cmp::Reverse;
+use std::{cmp::Reverse, collections::BTreeSet};
 
+use anyhow::{Result, bail};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
 use smallvec::{SmallVec, smallvec};
+use uuid::Uuid;
 
 use crate::{
+    config::{BandAction, InstrumentConfig, TradingRules},
     matcher::Matcher,
     model::{
-        BookOrder, CancelReason, EngineEvent, IncomingOrder, OrderSide, OrderType, Price,
-        ProcessOrder, TimeInForce, TradeId,
+        Amount, AuctionPrice, BookOrder, CancelReason, Command, EngineEvent, EventEnvelope,
+        ExecutionReport, IncomingOrder, MarketDataMessage, OrderBookMessage, OrderId,
+        OrderProgress, OrderSide, OrderStatus, OrderType, PRICE_SCALE, Price, PriceLevel, Prices,
+        ProcessOrder, RejectReason, RestingOrder, SelfTradeAction, StatusReason, Symbol, TickSize,
+        TimeInForce, Timestamp, TradeId, TradingStatus,
     },
     policies::PolicyChecker,
-    storage::BookSide,
+    storage::{BookSide, PriceWindow, TriggerSide},
 };
 
+const DAY: Timestamp = 86_400 * 1_000_000_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineSnapshot {
+    pub symbol: Symbol,
+    pub asks: Vec<BookOrder>,
+    pub bids: Vec<BookOrder>,
+    pub buy_stops: Vec<(Price, ProcessOrder)>,
+    pub sell_stops: Vec<(Price, ProcessOrder)>,
+    pub last_trade_price: Option<Price>,
+    pub next_trade_id: TradeId,
+    #[serde(default)]
+    pub order_book_sequence: u64,
+    #[serde(default)]
+    pub event_sequence: u64,
+    #[serde(default)]
+    pub status: TradingStatus,
+    #[serde(default)]
+    pub recent_trades: Vec<(Timestamp, Price)>,
+    #[serde(default)]
+    pub indicative: Option<AuctionPrice>,
+    #[serde(default)]
+    pub session_time: Option<Timestamp>,
+    #[serde(default)]
+    pub expiries: Vec<(Timestamp, OrderId)>,
+    #[serde(default)]
+    pub orders: Vec<(OrderId, OrderProgress)>,
+}
+
+impl Prices for EngineSnapshot {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        self.asks.for_each_price(f);
+        self.bids.for_each_price(f);
+        for (stop_price, order) in self.buy_stops.iter_mut().chain(&mut self.sell_stops) {
+            f(stop_price);
+            order.for_each_price(f);
+        }
+        self.last_trade_price.for_each_price(f);
+        for (_, price) in &mut self.recent_trades {
+            f(price);
+        }
+        self.indicative.for_each_price(f);
+    }
+}
+
 pub struct MatchEngine {
+    symbol: Symbol,
     asks: BookSide<Price>,
     bids: BookSide<Reverse<Price>>,
+    buy_stops: TriggerSide<Price>,
+    sell_stops: TriggerSide<Reverse<Price>>,
+    last_trade_price: Option<Price>,
     next_trade_id: TradeId,
+    self_trade_action: SelfTradeAction,
+    tick_size: TickSize,
+    rules: TradingRules,
+    order_book_sequence: u64,
+    order_book_updates: Vec<OrderBookMessage>,
+    event_sequence: u64,
+    status: TradingStatus,
+    recent_trades: PriceWindow,
+    indicative: Option<AuctionPrice>,
+    session_time: Option<Timestamp>,
+    expiries: BTreeSet<(Timestamp, OrderId)>,
+    orders: FxHashMap<OrderId, OrderProgress>,
+    now: Timestamp,
 }
 
+// Prices cross the public methods in wire units and are held as whole ticks
+// inside, so a snapshot stays valid across a change of tick size as long as
+// its prices are on the new tick.
 impl MatchEngine {
-    pub fn new() -> Self {
+    pub fn new(instrument: &InstrumentConfig) -> Self {
+        let tick_size = TickSize::new(instrument.rules.tick_size);
+        // `load_instruments` rejects a reference price off the tick.
+        let rules = TradingRules {
+            reference_price: tick_size
+                .to_ticks(instrument.rules.reference_price)
+                .flatten(),
+            ..instrument.rules.clone()
+        };
         MatchEngine {
+            symbol: instrument.symbol.clone(),
             asks: BookSide::new(),
             bids: BookSide::new(),
+            buy_stops: TriggerSide::new(),
+            sell_stops: TriggerSide::new(),
+            last_trade_price: None,
             next_trade_id: 0,
+            self_trade_action: instrument.self_trade_action,
+            tick_size,
+            rules,
+            order_book_sequence: 0,
+            order_book_updates: Vec::new(),
+            event_sequence: 0,
+            status: TradingStatus::Continuous,
+            recent_trades: PriceWindow::new(),
+            indicative: None,
+            session_time: None,
+            expiries: BTreeSet::new(),
+            orders: FxHashMap::default(),
+            now: 0,
         }
     }
-    pub fn process(&mut self, order: IncomingOrder) -> SmallVec<[EngineEvent; 16]> {
-        match order.order_type {
-            OrderType::Market { .. } => self.handle_market(&mut ProcessOrder::from(order)),
-            OrderType::Limit { .. } => self.handle_limit(&mut ProcessOrder::from(order)),
+
+    pub fn snapshot(&self) -> EngineSnapshot {
+        self.tick_size.to_wire(EngineSnapshot {
+            symbol: self.symbol.clone(),
+            asks: self.asks.iter().cloned().collect(),
+            bids: self.bids.iter().cloned().collect(),
+            buy_stops: self
+                .buy_stops
+                .iter()
+                .map(|(stop_price, order)| (stop_price, order.clone()))
+                .collect(),
+            sell_stops: self
+                .sell_stops
+                .iter()
+                .map(|(stop_price, order)| (stop_price, order.clone()))
+                .collect(),
+            last_trade_price: self.last_trade_price,
+            next_trade_id: self.next_trade_id,
+            order_book_sequence: self.order_book_sequence,
+            event_sequence: self.event_sequence,
+            status: self.status,
+            recent_trades: self.recent_trades.entries(),
+            indicative: self.indicative,
+            session_time: self.session_time,
+            expiries: self.expiries.iter().copied().collect(),
+            orders: {
+                let mut orders: Vec<_> = self
+                    .orders
+                    .iter()
+                    .map(|(order_id, progress)| (*order_id, progress.clone()))
+                    .collect();
+                orders.sort_by_key(|(order_id, _)| *order_id);
+                orders
+            },
+        })
+    }
+
+    // Orders are re-inserted in snapshot order, which is priority order, so
+    // the restored queues match the ones that were captured.
+    pub fn restore(&mut self, snapshot: EngineSnapshot) -> Result<()> {
+        let Some(snapshot) = self.tick_size.to_ticks(snapshot) else {
+            bail!("Snapshot of {} has prices off its tick size", self.symbol);
+        };
+        self.asks = BookSide::new();
+        self.bids = BookSide::new();
+        self.buy_stops = TriggerSide::new();
+        self.sell_stops = TriggerSide::new();
+        for order in snapshot.asks {
+            self.asks.insert(order);
+        }
+        for order in snapshot.bids {
+            self.bids.insert(order);
+        }
+        for (stop_price, order) in snapshot.buy_stops {
+            self.buy_stops.insert(stop_price, order);
+        }
+        for (stop_price, order) in snapshot.sell_stops {
+            self.sell_stops.insert(stop_price, order);
         }
+        self.last_trade_price = snapshot.last_trade_price;
+        self.next_trade_id = snapshot.next_trade_id;
+        self.order_book_sequence = snapshot.order_book_sequence;
+        self.event_sequence = snapshot.event_sequence;
+        self.status = snapshot.status;
+        self.recent_trades = PriceWindow::new();
+        for (time, price) in snapshot.recent_trades {
+            self.recent_trades.push(time, price);
+        }
+        self.indicative = snapshot.indicative;
+        self.session_time = snapshot.session_time;
+        self.expiries = snapshot.expiries.into_iter().collect();
+        self.orders = snapshot.orders.into_iter().collect();
+        self.asks.drain_changes();
+        self.bids.drain_changes();
+        self.order_book_updates.clear();
+        Ok(())
     }
 
-    fn handle_market(&mut self, mut order: &mut ProcessOrder) -> SmallVec<[EngineEvent; 16]> {
-        match PolicyChecker::check_post_only(order) {
+    pub fn depth_updates(&mut self) -> Vec<MarketDataMessage> {
+        let bids = self
+            .bids
+            .drain_depth_changes()
+            .into_iter()
+            .map(|level| (OrderSide::Buy, level));
+        let asks = self
+            .asks
+            .drain_depth_changes()
+            .into_iter()
+            .map(|level| (OrderSide::Sell, level));
+        bids.chain(asks)
+            .map(|(side, (price, amount))| MarketDataMessage::DepthUpdate {
+                symbol: self.symbol.clone(),
+                side,
+                price: self.tick_size.to_wire(price),
+                amount,
+            })
+            .collect()
+    }
+
+    pub fn depth_snapshot(&self, levels: usize) -> MarketDataMessage {
+        let to_levels = |depth: Vec<(Price, Amount)>| {
+            depth
+                .into_iter()
+                .map(|(price, amount)| PriceLevel { price, amount })
+                .collect()
+        };
+        self.tick_size.to_wire(MarketDataMessage::DepthSnapshot {
+            symbol: self.symbol.clone(),
+            bids: to_levels(self.bids.depth(levels)),
+            asks: to_levels(self.asks.depth(levels)),
+        })
+    }
+
+    pub fn order_book_updates(&mut self) -> Vec<OrderBookMessage> {
+        self.tick_size
+            .to_wire(std::mem::take(&mut self.order_book_updates))
+    }
+
+    pub fn order_book_snapshot(&self) -> OrderBookMessage {
+        let to_orders = |orders: Vec<&BookOrder>| {
+            orders
+                .into_iter()
+                .map(|order| RestingOrder {
+                    order_id: order.order_id,
+                    price: order.price,
+                    amount: order.amount,
+                })
+                .collect()
+        };
+        self.tick_size.to_wire(OrderBookMessage::Snapshot {
+            symbol: self.symbol.clone(),
+            sequence: self.order_book_sequence,
+            bids: to_orders(self.bids.iter().collect()),
+            asks: to_orders(self.asks.iter().collect()),
+        })
+    }
+
+    // Every event gets the next per-symbol sequence number, so consumers of
+    // the symbol's partition can spot gaps and drop redelivered duplicates.
+    pub fn process(
+        &mut self,
+        command: Command,
+        ingress_time: Timestamp,
+        match_time: Timestamp,
+    ) -> SmallVec<[EventEnvelope; 16]> {
+        self.now = ingress_time;
+        // Rejecting a cancel or amend leaves the order it names untouched, and
+        // so does rejecting a new order that reuses a live order's id.
+        let request_id = match &command {
+            Command::CancelOrder { order_id, .. } | Command::AmendOrder { order_id, .. } => {
+                Some(*order_id)
+            }
+            Command::NewOrder(order) if self.is_live(order.order_id) => Some(order.order_id),
+            _ => None,
+        };
+        let mut session_events = self.advance_session(ingress_time);
+        session_events.extend(self.expire_orders(ingress_time));
+        let order_id = command.order_id();
+        let events = match self.tick_size.to_ticks(command) {
+            Some(command) => self.handle_command(command),
+            // Only orders and amends carry prices.
+            None => order_id
+                .map(|order_id| EngineEvent::OrderRejected {
+                    order_id,
+                    reason: RejectReason::InvalidPrice,
+                })
+                .into_iter()
+                .collect(),
+        };
+        session_events.extend(events);
+        let mut events = session_events;
+        self.activate_stops(&mut events);
+        self.check_circuit_breaker(&mut events, ingress_time);
+        events.extend(self.publish_indicative());
+        self.record_book_changes();
+        let tick_size = self.tick_size;
+        self.report_executions(events, request_id)
+            .into_iter()
+            .map(|event| {
+                self.event_sequence += 1;
+                EventEnvelope {
+                    symbol: self.symbol.clone(),
+                    sequence: self.event_sequence,
+                    ingress_time,
+                    match_time,
+                    event: tick_size.to_wire(event),
+                }
+            })
+            .collect()
+    }
+
+    fn handle_command(&mut self, command: Command) -> SmallVec<[EngineEvent; 16]> {
+        match command {
+            Command::NewOrder(order) => self.handle_new_order(order),
+            Command::CancelOrder {
+                order_id, user_id, ..
+            } => self.handle_cancel(order_id, user_id),
+            Command::CancelAllOrders { user_id, .. } => self.handle_cancel_all(user_id),
+            Command::AmendOrder {
+                order_id,
+                user_id,
+                price,
+                amount,
+                ..
+            } => self.handle_amend(order_id, user_id, price, amount),
+            Command::Halt { .. } => self.transition(TradingStatus::Halted, StatusReason::Manual),
+            Command::Resume { .. } => {
+                self.transition(TradingStatus::Continuous, StatusReason::Manual)
+            }
+            Command::StartAuction { .. } => {
+                self.transition(TradingStatus::Auction, StatusReason::Manual)
+            }
+            Command::SetTradingStatus { status, .. } => {
+                self.transition(status, StatusReason::Manual)
+            }
+            Command::Tick { .. } => SmallVec::new(),
+        }
+    }
+
+    fn handle_new_order(&mut self, order: IncomingOrder) -> SmallVec<[EngineEvent; 16]> {
+        if self.status == TradingStatus::Closed {
+            return smallvec![EngineEvent::OrderRejected {
+                order_id: order.order_id,
+                reason: RejectReason::MarketClosed,
+            }];
+        }
+        if self.is_live(order.order_id) {
+            return smallvec![EngineEvent::OrderRejected {
+                order_id: order.order_id,
+                reason: RejectReason::DuplicateOrderId,
+            }];
+        }
+        let stop_price = match order.order_type {
+            OrderType::Market { .. } | OrderType::Limit { .. } => None,
+            OrderType::StopMarket { stop_price } | OrderType::StopLimit { stop_price, .. } => {
+                Some(stop_price)
+            }
+        };
+        let mut order = ProcessOrder::from(order);
+        if let TimeInForce::DAY = order.tif {
+            order.tif = TimeInForce::GTD(self.day_expiry());
+        }
+        match PolicyChecker::check_trading_rules(&order, stop_price, &self.rules, self.tick_size) {
+            Ok(_) => (),
+            Err(event) => return smallvec![event],
+        };
+        match PolicyChecker::check_expiry(&order, self.now) {
+            Ok(_) => (),
+            Err(event) => return smallvec![event],
+        };
+        let accepted = EngineEvent::OrderAccepted {
+            order_id: order.order_id,
+            user_id: order.user_id,
+            side: order.side,
+            amount: order.amount,
+        };
+        let order_id = order.order_id;
+        let mut executed_events = match stop_price {
+            Some(stop_price) => self.handle_stop(order, stop_price),
+            None if order.is_market => self.handle_market(&mut order),
+            None => self.handle_limit(&mut order),
+        };
+        let rejected = executed_events.iter().any(|event| {
+            matches!(event, EngineEvent::OrderRejected { order_id: rejected_id, .. } if *rejected_id == order_id)
+        });
+        if !rejected {
+            executed_events.insert(0, accepted);
+        }
+        executed_events
+    }
+
+    // Follows every event that changes an order's state with an execution
+    // report carrying the order's cumulative fills.
+    fn report_executions(
+        &mut self,
+        events: SmallVec<[EngineEvent; 16]>,
+        request_id: Option<OrderId>,
+    ) -> SmallVec<[EngineEvent; 16]> {
+        let tick_size = self.tick_size;
+        let mut reported = SmallVec::with_capacity(events.len());
+        for event in events {
+            let reports: SmallVec<[ExecutionReport; 2]> = match &event {
+                EngineEvent::OrderAccepted {
+                    order_id, amount, ..
+                } => {
+                    let progress = OrderProgress::new(*amount);
+                    let report = progress.report(*order_id, OrderStatus::New);
+                    self.orders.insert(*order_id, progress);
+                    smallvec![report]
+                }
+                EngineEvent::TradeExecuted(trade) => [trade.maker_order_id, trade.taker_order_id]
+                    .into_iter()
+                    .filter_map(|order_id| {
+                        let progress = self.orders.get_mut(&order_id)?;
+                        progress.fill(tick_size.to_wire(trade.price), trade.amount);
+                        let status = progress.status();
+                        let report = progress.report(order_id, status);
+                        if status == OrderStatus::Filled {
+                            self.orders.remove(&order_id);
+                        }
+                        Some(report)
+                    })
+                    .collect(),
+                EngineEvent::OrderCancelled { order_id, .. } => self
+                    .orders
+                    .remove(order_id)
+                    .map(|progress| progress.report(*order_id, OrderStatus::Cancelled))
+                    .into_iter()
+                    .collect(),
+                EngineEvent::OrderRejected { order_id, .. } if request_id != Some(*order_id) => {
+                    let progress = self.orders.remove(order_id).unwrap_or_default();
+                    smallvec![progress.report(*order_id, OrderStatus::Rejected)]
+                }
+                EngineEvent::OrderAmended {
+                    order_id,
+                    new_amount,
+                    ..
+                } => match self.orders.get_mut(order_id) {
+                    Some(progress) => {
+                        progress.amount = progress.filled_amount + new_amount;
+                        smallvec![progress.report(*order_id, progress.status())]
+                    }
+                    None => SmallVec::new(),
+                },
+                _ => SmallVec::new(),
+            };
+            reported.push(event);
+            reported.extend(reports.into_iter().map(EngineEvent::ExecutionReport));
+        }
+        reported
+    }
+
+    fn handle_stop(
+        &mut self,
+        mut order: ProcessOrder,
+        stop_price: Price,
+    ) -> SmallVec<[EngineEvent; 16]> {
+        let triggered_by = self
+            .last_trade_price
+            .filter(|_| self.status == TradingStatus::Continuous)
+            .filter(|last| match order.side {
+                OrderSide::Buy => *last >= stop_price,
+                OrderSide::Sell => *last <= stop_price,
+            });
+        if let Some(trade_price) = triggered_by {
+            let mut executed_events: SmallVec<[EngineEvent; 16]> =
+                smallvec![EngineEvent::StopTriggered {
+                    order_id: order.order_id,
+                    stop_price,
+                    trade_price,
+                }];
+            executed_events.extend(self.execute(&mut order));
+            return executed_events;
+        }
+        let event = EngineEvent::StopOrderPlaced {
+            order_id: order.order_id,
+            side: order.side,
+            stop_price,
+        };
+        self.schedule_expiry(&order);
+        match order.side {
+            OrderSide::Buy => self.buy_stops.insert(stop_price, order),
+            OrderSide::Sell => self.sell_stops.insert(stop_price, order),
+        };
+        smallvec![event]
+    }
+
+    // Triggers stops against every trade produced so far in this call, so a
+    // triggered stop whose own trades cross further stops cascades here too.
+    // As on arrival, stops only trigger in continuous trading; a closing
+    // uncross leaves them waiting for the next session.
+    fn activate_stops(&mut self, events: &mut SmallVec<[EngineEvent; 16]>) {
+        if self.status != TradingStatus::Continuous {
+            return;
+        }
+        let mut scanned = 0;
+        let mut low: Option<Price> = None;
+        let mut high: Option<Price> = None;
+        loop {
+            for event in &events[scanned..] {
+                if let EngineEvent::TradeExecuted(trade) = event {
+                    low = Some(low.map_or(trade.price, |low| low.min(trade.price)));
+                    high = Some(high.map_or(trade.price, |high| high.max(trade.price)));
+                }
+            }
+            scanned = events.len();
+
+            let buy_trigger = high.and_then(|high| {
+                self.buy_stops
+                    .pop_triggered(high)
+                    .map(|(stop_price, order)| (stop_price, high, order))
+            });
+            let triggered = buy_trigger.or_else(|| {
+                low.and_then(|low| {
+                    self.sell_stops
+                        .pop_triggered(low)
+                        .map(|(stop_price, order)| (stop_price, low, order))
+                })
+            });
+            let Some((stop_price, trade_price, mut order)) = triggered else {
+                break;
+            };
+            events.push(EngineEvent::StopTriggered {
+                order_id: order.order_id,
+                stop_price,
+                trade_price,
+            });
+            let executed_events = self.execute(&mut order);
+            events.extend(executed_events);
+        }
+    }
+
+    fn execute(&mut self, order: &mut ProcessOrder) -> SmallVec<[EngineEvent; 16]> {
+        if order.is_market {
+            self.handle_market(order)
+        } else {
+            self.handle_limit(order)
+        }
+    }
+
+    fn handle_cancel(&mut self, order_id: OrderId, user_id: Uuid) -> SmallVec<[EngineEvent; 16]> {
+        if let Some(stop) = self
+            .buy_stops
+            .get(order_id)
+            .or_else(|| self.sell_stops.get(order_id))
+        {
+            if stop.user_id != user_id {
+                return smallvec![EngineEvent::OrderRejected {
+                    order_id,
+                    reason: RejectReason::NotOrderOwner,
+                }];
+            }
+            return self
+                .cancel_stop(order_id, CancelReason::UserRequest)
+                .into_iter()
+                .collect();
+        }
+        if let Err(event) = self.check_ownership(order_id, user_id) {
+            return smallvec![event];
+        }
+        self.cancel_resting(order_id, CancelReason::UserRequest)
+            .into_iter()
+            .collect()
+    }
+
+    fn handle_amend(
+        &mut self,
+        order_id: OrderId,
+        user_id: Uuid,
+        price: Option<Price>,
+        amount: Option<Amount>,
+    ) -> SmallVec<[EngineEvent; 16]> {
+        let (side, resting) = match self.check_ownership(order_id, user_id) {
+            Ok(found) => found,
+            Err(event) => return smallvec![event],
+        };
+        let new_price = price.unwrap_or(resting.price);
+        let new_amount = amount.unwrap_or(resting.total_amount());
+        let mut order = ProcessOrder {
+            order_id,
+            user_id,
+            side,
+            amount: new_amount,
+            price: new_price,
+            post_only: resting.post_only,
+            is_market: false,
+            tif: TimeInForce::GTC,
+            display_amount: resting.display_amount,
+            self_trade_action: resting.self_trade_action,
+            market_to_limit: false,
+            min_qty: None,
+            all_or_none: resting.all_or_none,
+        };
+        // A price-only amend keeps the remaining size, which partial fills may
+        // have taken below the size and notional minimums the order first met.
+        let rules_check = match amount {
+            Some(_) => {
+                PolicyChecker::check_trading_rules(&order, None, &self.rules, self.tick_size)
+            }
+            None if PolicyChecker::is_valid_price(new_price) => Ok(()),
+            None => Err(EngineEvent::OrderRejected {
+                order_id,
+                reason: RejectReason::InvalidPrice,
+            }),
+        };
+        match rules_check {
             Ok(_) => (),
             Err(event) => return smallvec![event],
+        };
+        let band_limit = self.band_limit(&order);
+        if new_price != resting.price && PolicyChecker::is_outside_band(side, new_price, band_limit)
+        {
+            return smallvec![EngineEvent::OrderRejected {
+                order_id,
+                reason: RejectReason::PriceBandViolation,
+            }];
+        }
+        match self.check_trading_status(&order) {
+            Ok(_) => (),
+            Err(event) => return smallvec![event],
+        };
+        // A post-only order moved through the spread would trade as the
+        // taker, so the amend is refused and the order stays as it was.
+        if order.post_only
+            && new_price != resting.price
+            && self.status == TradingStatus::Continuous
+            && self.crosses(&order)
+        {
+            return smallvec![EngineEvent::OrderRejected {
+                order_id,
+                reason: RejectReason::PostOnlyViolation,
+            }];
         }
+
+        let mut executed_events: SmallVec<[EngineEvent; 16]> =
+            smallvec![EngineEvent::OrderAmended {
+                order_id,
+                side,
+                old_price: resting.price,
+                new_price,
+                old_amount: resting.total_amount(),
+                new_amount,
+            }];
+
+        // Reducing size keeps the order's place in the queue; anything else
+        // is a cancel-replace and goes to the back of the new price level.
+        if new_price == resting.price && new_amount <= resting.total_amount() {
+            match side {
+                OrderSide::Buy => self.bids.reduce(order_id, new_amount),
+                OrderSide::Sell => self.asks.reduce(order_id, new_amount),
+            };
+            return executed_events;
+        }
+
+        match side {
+            OrderSide::Buy => self.bids.remove(order_id),
+            OrderSide::Sell => self.asks.remove(order_id),
+        };
+        if new_price != resting.price && self.status == TradingStatus::Continuous {
+            executed_events.extend(self.match_order(&mut order, band_limit));
+        }
+        if order.amount > 0 {
+            let book_order = BookOrder::from(&order);
+            match side {
+                OrderSide::Buy => self.bids.insert(book_order),
+                OrderSide::Sell => self.asks.insert(book_order),
+            };
+        }
+        executed_events
+    }
+
+    // Whether `order` would take liquidity: it reaches the best price open to
+    // everyone, or an all-or-none order it is large enough to fill.
+    fn crosses(&self, order: &ProcessOrder) -> bool {
+        let best_price = match order.side {
+            OrderSide::Buy => self.asks.best_price(),
+            OrderSide::Sell => self.bids.best_price(),
+        };
+        best_price.is_some_and(|best_price| {
+            PolicyChecker::check_price_match(order.side, best_price, order.price, order.is_market)
+        }) || self.fillable_amount(order, self.band_limit(order)) > 0
+    }
+
+    fn fillable_amount(&self, order: &ProcessOrder, band_limit: Option<Price>) -> Amount {
+        let self_trade_action = self.self_trade_action(order);
         match order.side {
             OrderSide::Buy => {
-                Matcher::hard_match(&mut order, &mut self.asks, &mut self.next_trade_id)
+                Matcher::fillable_amount(order, &self.asks, self_trade_action, band_limit)
             }
             OrderSide::Sell => {
-                Matcher::hard_match(&mut order, &mut self.bids, &mut self.next_trade_id)
+                Matcher::fillable_amount(order, &self.bids, self_trade_action, band_limit)
             }
         }
-        .iter()
-        .map(|trade| EngineEvent::TradeExecuted(*trade))
-        .collect()
     }
 
-    fn handle_limit(&mut self, mut order: &mut ProcessOrder) -> SmallVec<[EngineEvent; 16]> {
+    // Whether `order_id` names an order that is resting, waiting on its stop
+    // or still being reported on.
+    fn is_live(&self, order_id: OrderId) -> bool {
+        self.asks.get(order_id).is_some()
+            || self.bids.get(order_id).is_some()
+            || self.buy_stops.get(order_id).is_some()
+            || self.sell_stops.get(order_id).is_some()
+            || self.orders.contains_key(&order_id)
+    }
+
+    fn check_ownership(
+        &self,
+        order_id: OrderId,
+        user_id: Uuid,
+    ) -> Result<(OrderSide, BookOrder), EngineEvent> {
+        let (side, order) = match self.asks.get(order_id) {
+            Some(order) => (OrderSide::Sell, order),
+            None => match self.bids.get(order_id) {
+                Some(order) => (OrderSide::Buy, order),
+                None => {
+                    return Err(EngineEvent::OrderRejected {
+                        order_id,
+                        reason: RejectReason::OrderNotFound,
+                    });
+                }
+            },
+        };
+        if order.user_id != user_id {
+            return Err(EngineEvent::OrderRejected {
+                order_id,
+                reason: RejectReason::NotOrderOwner,
+            });
+        }
+        Ok((side, order.clone()))
+    }
+
+    fn handle_cancel_all(&mut self, user_id: Uuid) -> SmallVec<[EngineEvent; 16]> {
+        let order_ids: SmallVec<[OrderId; 16]> = self
+            .asks
+            .iter()
+            .chain(self.bids.iter())
+            .filter(|order| order.user_id == user_id)
+            .map(|order| order.order_id)
+            .collect();
+        let stop_ids: SmallVec<[OrderId; 16]> = self
+            .buy_stops
+            .iter()
+            .chain(self.sell_stops.iter())
+            .map(|(_, order)| order)
+            .filter(|order| order.user_id == user_id)
+            .map(|order| order.order_id)
+            .collect();
+        let mut executed_events: SmallVec<[EngineEvent; 16]> = order_ids
+            .into_iter()
+            .filter_map(|order_id| self.cancel_resting(order_id, CancelReason::UserRequest))
+            .collect();
+        executed_events.extend(
+            stop_ids
+                .into_iter()
+                .filter_map(|order_id| self.cancel_stop(order_id, CancelReason::UserRequest)),
+        );
+        executed_events
+    }
+
+    fn cancel_stop(&mut self, order_id: OrderId, reason: CancelReason) -> Option<EngineEvent> {
+        let order = self
+            .buy_stops
+            .remove(order_id)
+            .or_else(|| self.sell_stops.remove(order_id))?;
+        Some(EngineEvent::OrderCancelled {
+            order_id: order.order_id,
+            remaining_amount: order.amount,
+            reason,
+        })
+    }
+
+    fn cancel_resting(&mut self, order_id: OrderId, reason: CancelReason) -> Option<EngineEvent> {
+        let order = self
+            .asks
+            .remove(order_id)
+            .or_else(|| self.bids.remove(order_id))?;
+        Some(EngineEvent::OrderCancelled {
+            order_id: order.order_id,
+            remaining_amount: order.total_amount(),
+            reason,
+        })
+    }
+
+    fn handle_market(&mut self, order: &mut ProcessOrder) -> SmallVec<[EngineEvent; 16]> {
+        match self.check_trading_status(order) {
+            Ok(_) => (),
+            Err(event) => return smallvec![event],
+        }
         match PolicyChecker::check_post_only(order) {
             Ok(_) => (),
             Err(event) => return smallvec![event],
-        };
+        }
+        let band_limit = self.band_limit(order);
+        let self_trade_action = self.self_trade_action(order);
         let liquidity_check_result = match order.side {
-            OrderSide::Buy => PolicyChecker::check_liquidity(order, &mut self.asks),
-            OrderSide::Sell => PolicyChecker::check_liquidity(order, &mut self.bids),
+            OrderSide::Buy => {
+                PolicyChecker::check_liquidity(order, &self.asks, self_trade_action, band_limit)
+            }
+            OrderSide::Sell => {
+                PolicyChecker::check_liquidity(order, &self.bids, self_trade_action, band_limit)
+            }
         };
         match liquidity_check_result {
             Ok(_) => (),
             Err(event) => return smallvec![event],
         };
-        let mut executed_events: SmallVec<[EngineEvent; 16]> = match order.side {
+        let mut executed_events = self.match_order(order, band_limit);
+        if order.amount == 0 {
+            return executed_events;
+        }
+        let best_price = match order.side {
+            OrderSide::Buy => self.asks.best_price(),
+            OrderSide::Sell => self.bids.best_price(),
+        };
+        let last_fill_price = executed_events.iter().rev().find_map(|event| match event {
+            EngineEvent::TradeExecuted(trade) if trade.taker_order_id == order.order_id => {
+                Some(trade.price)
+            }
+            _ => None,
+        });
+        if best_price
+            .is_some_and(|price| PolicyChecker::is_outside_band(order.side, price, band_limit))
+        {
+            executed_events.push(Self::band_cancel(order));
+        } else if let Some(price) = last_fill_price
+            && order.market_to_limit
+            && !matches!(order.tif, TimeInForce::IOC | TimeInForce::FOK)
+        {
+            // The remainder becomes an ordinary limit order at the price the
+            // sweep stopped at; an order that never traded has no such price.
+            order.is_market = false;
+            order.price = price;
+            executed_events.push(self.rest(order));
+        } else {
+            executed_events.push(EngineEvent::OrderCancelled {
+                order_id: order.order_id,
+                remaining_amount: order.amount,
+                reason: CancelReason::NoLiquidity,
+            });
+        }
+        executed_events
+    }
+
+    fn handle_limit(&mut self, order: &mut ProcessOrder) -> SmallVec<[EngineEvent; 16]> {
+        match self.check_trading_status(order) {
+            Ok(_) => (),
+            Err(event) => return smallvec![event],
+        };
+        match PolicyChecker::check_post_only(order) {
+            Ok(_) => (),
+            Err(event) => return smallvec![event],
+        };
+        match PolicyChecker::check_display_amount(order) {
+            Ok(_) => (),
+            Err(event) => return smallvec![event],
+        };
+        // Orders collected for an auction rest even when they cross; they
+        // trade at the uncross.
+        if self.status.collects_orders() {
+            return smallvec![self.rest(order)];
+        }
+        let band_limit = self.band_limit(order);
+        let outside_band = PolicyChecker::is_outside_band(order.side, order.price, band_limit);
+        if outside_band && self.rules.band_action == BandAction::Reject {
+            return smallvec![EngineEvent::OrderRejected {
+                order_id: order.order_id,
+                reason: RejectReason::PriceBandViolation,
+            }];
+        }
+        let self_trade_action = self.self_trade_action(order);
+        let liquidity_check_result = match order.side {
             OrderSide::Buy => {
-                Matcher::hard_match(&mut order, &mut self.asks, &mut self.next_trade_id)
+                PolicyChecker::check_liquidity(order, &self.asks, self_trade_action, band_limit)
             }
             OrderSide::Sell => {
-                Matcher::hard_match(&mut order, &mut self.bids, &mut self.next_trade_id)
+                PolicyChecker::check_liquidity(order, &self.bids, self_trade_action, band_limit)
             }
-        }
-        .iter()
-        .map(|trade| EngineEvent::TradeExecuted(*trade))
-        .collect();
-        if order.amount > 0 {
+        };
+        match liquidity_check_result {
+            Ok(_) => (),
+            Err(event) => return smallvec![event],
+        };
+        let mut executed_events = self.match_order(order, band_limit);
+        // A remainder priced past the band would cross the book it was
+        // stopped from trading against, so it never rests.
+        if order.amount > 0 && outside_band {
+            executed_events.push(Self::band_cancel(order));
+        } else if order.amount > 0 {
             match order.tif {
-                TimeInForce::GTC => {
-                    let book_order = BookOrder {
-                        user_id: order.user_id,
-                        order_id: order.order_id,
-                        price: order.price,
-                        amount: order.amount,
-                    };
-                    match order.side {
-                        OrderSide::Buy => self.bids.insert(book_order.clone()),
-                        OrderSide::Sell => self.asks.insert(book_order.clone()),
-                    };
-                    executed_events.push(EngineEvent::OrderPlaced {
-                        order: book_order,
-                        side: order.side,
-                    });
+                TimeInForce::GTC | TimeInForce::GTD(_) | TimeInForce::DAY => {
+                    executed_events.push(self.rest(order))
                 }
                 TimeInForce::IOC => {
                     executed_events.push(EngineEvent::OrderCancelled {
@@ -100,9 +903,360 @@ impl MatchEngine {
                         reason: CancelReason::IocExpired,
                     });
                 }
-                TimeInForce::FOK => (),
+                // Unreachable after the dry run, but never drop a remainder.
+                TimeInForce::FOK => {
+                    executed_events.push(EngineEvent::OrderCancelled {
+                        order_id: order.order_id,
+                        remaining_amount: order.amount,
+                        reason: CancelReason::FokLiquidityShortage,
+                    });
+                }
             }
         }
         executed_events
     }
+
+    fn rest(&mut self, order: &ProcessOrder) -> EngineEvent {
+        self.schedule_expiry(order);
+        let book_order = BookOrder::from(order);
+        match order.side {
+            OrderSide::Buy => self.bids.insert(book_order.clone()),
+            OrderSide::Sell => self.asks.insert(book_order.clone()),
+        };
+        EngineEvent::OrderPlaced {
+            order: book_order,
+            side: order.side,
+        }
+    }
+
+    // `band_limit` is the one the caller already checked the order against.
+    fn match_order(
+        &mut self,
+        order: &mut ProcessOrder,
+        band_limit: Option<Price>,
+    ) -> SmallVec<[EngineEvent; 16]> {
+        let self_trade_action = self.self_trade_action(order);
+        // An all-or-none order trades its whole size or rests untouched. A
+        // resting one stays out of the best price and the depth feed, so it
+        // never shows as crossing the book.
+        if order.all_or_none && self.fillable_amount(order, band_limit) < order.amount {
+            return SmallVec::new();
+        }
+        self.record_book_changes();
+        let executed_events = match order.side {
+            OrderSide::Buy => Matcher::hard_match(
+                order,
+                &mut self.asks,
+                &mut self.next_trade_id,
+                self_trade_action,
+                band_limit,
+            ),
+            OrderSide::Sell => Matcher::hard_match(
+                order,
+                &mut self.bids,
+                &mut self.next_trade_id,
+                self_trade_action,
+                band_limit,
+            ),
+        };
+        if let Some(trade_price) = executed_events.iter().rev().find_map(|event| match event {
+            EngineEvent::TradeExecuted(trade) => Some(trade.price),
+            _ => None,
+        }) {
+            self.last_trade_price = Some(trade_price);
+        }
+        self.record_book_changes();
+        executed_events
+    }
+
+    fn check_trading_status(&self, order: &ProcessOrder) -> Result<(), EngineEvent> {
+        match order.side {
+            OrderSide::Buy => PolicyChecker::check_trading_status(order, self.status, &self.asks),
+            OrderSide::Sell => PolicyChecker::check_trading_status(order, self.status, &self.bids),
+        }
+    }
+
+    // Reopening or closing uncrosses whatever crossed while matching was off,
+    // which is how pre-open and auction phases end; the book is never
+    // continuous and crossed at the same time.
+    fn transition(
+        &mut self,
+        status: TradingStatus,
+        reason: StatusReason,
+    ) -> SmallVec<[EngineEvent; 16]> {
+        if self.status == status {
+            return SmallVec::new();
+        }
+        let mut executed_events = match status {
+            TradingStatus::Continuous | TradingStatus::Closed => self.uncross(),
+            TradingStatus::PreOpen | TradingStatus::Halted | TradingStatus::Auction => {
+                SmallVec::new()
+            }
+        };
+        executed_events.extend(self.set_status(status, reason));
+        executed_events
+    }
+
+    // Fires every scheduled transition between the last command and `now`, in
+    // order. The first command after startup, or after more than a day of
+    // silence, moves straight to the phase in effect. A halted instrument
+    // stays halted until resumed, so only the close still applies to it.
+    fn advance_session(&mut self, now: Timestamp) -> SmallVec<[EngineEvent; 16]> {
+        let mut executed_events = SmallVec::new();
+        for status in self.due_transitions(now) {
+            if self.status == TradingStatus::Halted && status != TradingStatus::Closed {
+                continue;
+            }
+            executed_events.extend(self.transition(status, StatusReason::Schedule));
+        }
+        if !self.rules.schedule.is_empty() {
+            self.session_time = Some(self.session_time.map_or(now, |last| last.max(now)));
+        }
+        executed_events
+    }
+
+    // Expiries are keyed by order id only, so an order keeps its expiry across
+    // amends and stop triggers. Entries of orders that are already gone are
+    // dropped when they come due.
+    fn schedule_expiry(&mut self, order: &ProcessOrder) {
+        if let TimeInForce::GTD(expiry) = order.tif {
+            self.expiries.insert((expiry, order.order_id));
+        }
+    }
+
+    fn expire_orders(&mut self, now: Timestamp) -> SmallVec<[EngineEvent; 16]> {
+        let mut executed_events = SmallVec::new();
+        while let Some(&(expiry, order_id)) = self.expiries.first()
+            && expiry <= now
+        {
+            self.expiries.pop_first();
+            executed_events.extend(
+                self.cancel_resting(order_id, CancelReason::Expired)
+                    .or_else(|| self.cancel_stop(order_id, CancelReason::Expired)),
+            );
+        }
+        executed_events
+    }
+
+    pub fn has_due_expiry(&self, now: Timestamp) -> bool {
+        self.expiries
+            .first()
+            .is_some_and(|(expiry, _)| *expiry <= now)
+    }
+
+    // The next scheduled close, or the next midnight UTC when the schedule
+    // never closes.
+    fn day_expiry(&self) -> Timestamp {
+        let today = self.now.div_euclid(DAY) * DAY;
+        [today, today + DAY]
+            .into_iter()
+            .flat_map(|day| {
+                self.rules
+                    .schedule
+                    .iter()
+                    .filter(|transition| transition.status == TradingStatus::Closed)
+                    .map(move |transition| day + transition.at.nanos())
+            })
+            .find(|close| *close > self.now)
+            .unwrap_or(today + DAY)
+    }
+
+    pub fn has_due_transition(&self, now: Timestamp) -> bool {
+        !self.due_transitions(now).is_empty()
+    }
+
+    fn due_transitions(&self, now: Timestamp) -> Vec<TradingStatus> {
+        let schedule = &self.rules.schedule;
+        let in_effect = || {
+            let today = now.div_euclid(DAY) * DAY;
+            schedule
+                .iter()
+                .rev()
+                .find(|transition| today + transition.at.nanos() <= now)
+                .or(schedule.last())
+                .map(|transition| transition.status)
+        };
+        let last = match self.session_time {
+            Some(last) if now - last <= DAY => last,
+            _ => return in_effect().into_iter().collect(),
+        };
+        (last.div_euclid(DAY)..=now.div_euclid(DAY))
+            .flat_map(|day| {
+                schedule
+                    .iter()
+                    .map(move |transition| (day * DAY + transition.at.nanos(), transition.status))
+            })
+            .filter(|(instant, _)| *instant > last && *instant <= now)
+            .map(|(_, status)| status)
+            .collect()
+    }
+
+    fn uncross(&mut self) -> SmallVec<[EngineEvent; 16]> {
+        self.indicative = None;
+        let Some(auction) = self.auction_price() else {
+            return SmallVec::new();
+        };
+        self.record_book_changes();
+        let executed_events = Matcher::uncross(
+            &mut self.bids,
+            &mut self.asks,
+            auction.price,
+            auction.volume,
+            &mut self.next_trade_id,
+        );
+        self.last_trade_price = Some(auction.price);
+        self.record_book_changes();
+        executed_events
+    }
+
+    fn publish_indicative(&mut self) -> Option<EngineEvent> {
+        if self.status != TradingStatus::Auction {
+            return None;
+        }
+        let indicative = self.auction_price();
+        if indicative == self.indicative {
+            return None;
+        }
+        self.indicative = indicative;
+        Some(EngineEvent::IndicativeUncross { indicative })
+    }
+
+    // The uncross price executes the most volume. Ties go to the smallest
+    // imbalance, then to the side of a one-sided surplus, then to the price
+    // closest to the reference price.
+    fn auction_price(&self) -> Option<AuctionPrice> {
+        let mut candidates: Vec<AuctionPrice> = self
+            .bids
+            .levels()
+            .chain(self.asks.levels())
+            .map(|(price, _)| {
+                let buy_volume = self.bids.get_liquidity(price);
+                let sell_volume = self.asks.get_liquidity(price);
+                AuctionPrice {
+                    price,
+                    volume: buy_volume.min(sell_volume),
+                    buy_volume,
+                    sell_volume,
+                }
+            })
+            .filter(|auction| auction.volume > 0)
+            .collect();
+
+        let max_volume = candidates.iter().map(|auction| auction.volume).max()?;
+        candidates.retain(|auction| auction.volume == max_volume);
+        let imbalance = |auction: &AuctionPrice| auction.buy_volume.abs_diff(auction.sell_volume);
+        let min_imbalance = candidates.iter().map(imbalance).min()?;
+        candidates.retain(|auction| imbalance(auction) == min_imbalance);
+
+        if candidates
+            .iter()
+            .all(|auction| auction.buy_volume > auction.sell_volume)
+        {
+            return candidates.into_iter().max_by_key(|auction| auction.price);
+        }
+        if candidates
+            .iter()
+            .all(|auction| auction.sell_volume > auction.buy_volume)
+        {
+            return candidates.into_iter().min_by_key(|auction| auction.price);
+        }
+        let reference_price = self.last_trade_price.or(self.rules.reference_price);
+        candidates.into_iter().min_by_key(|auction| {
+            (
+                reference_price.map(|reference| auction.price.units().abs_diff(reference.units())),
+                auction.price,
+            )
+        })
+    }
+
+    fn set_status(&mut self, status: TradingStatus, reason: StatusReason) -> Option<EngineEvent> {
+        if self.status == status {
+            return None;
+        }
+        self.status = status;
+        // Resuming starts a fresh window, or the move that tripped the
+        // breaker would halt the instrument again on the next trade.
+        if status == TradingStatus::Continuous {
+            self.recent_trades.clear();
+        }
+        Some(EngineEvent::TradingStatusChanged { status, reason })
+    }
+
+    // Trades are timed by the ingress time of the command that produced them,
+    // so a replay trips the breaker at exactly the same point.
+    fn check_circuit_breaker(&mut self, events: &mut SmallVec<[EngineEvent; 16]>, now: Timestamp) {
+        let Some(breaker) = &self.rules.circuit_breaker else {
+            return;
+        };
+        let threshold = breaker.threshold;
+        let window_start = now - breaker.window_ms * 1_000_000;
+        for event in events.iter() {
+            if let EngineEvent::TradeExecuted(trade) = event {
+                self.recent_trades.push(now, trade.price);
+            }
+        }
+        self.recent_trades.expire(window_start);
+
+        let Some((low, high)) = self.recent_trades.range() else {
+            return;
+        };
+        let (low, high) = (low.units() as i128, high.units() as i128);
+        let scale = PRICE_SCALE as i128;
+        // Only continuous trading is halted; a closing uncross can't reopen
+        // a closed instrument as halted.
+        if self.status == TradingStatus::Continuous
+            && low > 0
+            && (high - low) * scale > threshold.units() as i128 * low
+        {
+            events.extend(self.set_status(TradingStatus::Halted, StatusReason::VolatilityBreaker));
+        }
+    }
+
+    // Market orders are held to the collar, everything else to the band.
+    fn band_limit(&self, order: &ProcessOrder) -> Option<Price> {
+        let band = if order.is_market {
+            self.rules.market_collar.or(self.rules.price_band)
+        } else {
+            self.rules.price_band
+        };
+        let reference_price = self.last_trade_price.or(self.rules.reference_price);
+        PolicyChecker::band_limit(order.side, reference_price, band)
+    }
+
+    fn self_trade_action(&self, order: &ProcessOrder) -> SelfTradeAction {
+        order.self_trade_action.unwrap_or(self.self_trade_action)
+    }
+
+    fn band_cancel(order: &ProcessOrder) -> EngineEvent {
+        EngineEvent::OrderCancelled {
+            order_id: order.order_id,
+            remaining_amount: order.amount,
+            reason: CancelReason::PriceBand,
+        }
+    }
+
+    // Sequences the pending book mutations. Each book operation touches a
+    // single side, so draining between operations keeps the feed in the
+    // order the changes actually happened across both sides.
+    fn record_book_changes(&mut self) {
+        let bids = self
+            .bids
+            .drain_changes()
+            .into_iter()
+            .map(|change| (OrderSide::Buy, change));
+        let asks = self
+            .asks
+            .drain_changes()
+            .into_iter()
+            .map(|change| (OrderSide::Sell, change));
+        for (side, change) in bids.chain(asks) {
+            self.order_book_sequence += 1;
+            self.order_book_updates.push(OrderBookMessage::Update {
+                symbol: self.symbol.clone(),
+                sequence: self.order_book_sequence,
+                side,
+                change,
+            });
+        }
+    }
 }
@@ -0,0 +1,181 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{Result, bail};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    model::{Command, Timestamp},
+    registry::EngineRegistry,
+};
+
+// Each record is `[len: u32 LE][len crc32: u32 LE][crc32: u32 LE][payload:
+// len bytes]`, where the payload is a JSON encoded `JournalEntry`. The length
+// has its own checksum so a corrupt one can't pass for a torn tail.
+const HEADER_LEN: usize = 12;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub sequence: u64,
+    pub partition: i32,
+    pub offset: i64,
+    #[serde(default)]
+    pub ingress_time: Timestamp,
+    pub command: Command,
+}
+
+pub struct Journal {
+    writer: BufWriter<File>,
+    next_sequence: u64,
+}
+
+impl Journal {
+    // Opens the journal for appending and returns the entries already in it.
+    // A torn record at the tail (a crash mid-write) is truncated away, while
+    // any damage that can't be shown to be in the last record is treated as
+    // corruption.
+    pub fn open(path: &str) -> Result<(Self, Vec<JournalEntry>)> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(Path::new(path))?;
+
+        let mut raw = Vec::new();
+        file.read_to_end(&mut raw)?;
+        let (records, valid_len) = Self::decode(&raw)?;
+        let entries: Vec<JournalEntry> = records.into_iter().map(|(entry, _)| entry).collect();
+        if valid_len < raw.len() {
+            eprintln!(
+                "Truncating torn journal tail: {} bytes",
+                raw.len() - valid_len
+            );
+            file.set_len(valid_len as u64)?;
+        }
+        file.seek(SeekFrom::End(0))?;
+
+        let next_sequence = entries.last().map_or(0, |entry| entry.sequence + 1);
+        let journal = Journal {
+            writer: BufWriter::new(file),
+            next_sequence,
+        };
+        Ok((journal, entries))
+    }
+
+    pub fn append(
+        &mut self,
+        partition: i32,
+        offset: i64,
+        ingress_time: Timestamp,
+        command: &Command,
+    ) -> Result<u64> {
+        let entry = JournalEntry {
+            sequence: self.next_sequence,
+            partition,
+            offset,
+            ingress_time,
+            command: command.clone(),
+        };
+        let payload = serde_json::to_vec(&entry)?;
+        let len = (payload.len() as u32).to_le_bytes();
+
+        self.writer.write_all(&len)?;
+        self.writer
+            .write_all(&crc32fast::hash(&len).to_le_bytes())?;
+        self.writer
+            .write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+        self.writer.write_all(&payload)?;
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+
+        self.next_sequence += 1;
+        Ok(entry.sequence)
+    }
+
+    // Drops every entry after `sequence` (all of them for `None`). Used when
+    // the journal ran ahead of what was committed downstream, so those
+    // commands will be consumed and journaled again.
+    pub fn truncate_after(&mut self, sequence: Option<u64>) -> Result<()> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        let mut raw = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut raw)?;
+
+        let (records, _) = Self::decode(&raw)?;
+        let keep_len = match sequence {
+            Some(sequence) => records
+                .iter()
+                .find(|(entry, _)| entry.sequence == sequence)
+                .map_or(0, |(_, end)| *end),
+            None => 0,
+        };
+        if keep_len < raw.len() {
+            eprintln!(
+                "Truncating uncommitted journal tail: {} bytes",
+                raw.len() - keep_len
+            );
+            file.set_len(keep_len as u64)?;
+            file.sync_data()?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        self.next_sequence = sequence.map_or(0, |sequence| sequence + 1);
+        Ok(())
+    }
+
+    fn decode(raw: &[u8]) -> Result<(Vec<(JournalEntry, usize)>, usize)> {
+        let mut entries = Vec::new();
+        let mut position = 0;
+        while raw.len() - position >= HEADER_LEN {
+            let len_bytes = &raw[position..position + 4];
+            let len_checksum = u32::from_le_bytes(raw[position + 4..position + 8].try_into()?);
+            let checksum = u32::from_le_bytes(raw[position + 8..position + 12].try_into()?);
+            // A crash can only cut a header short, which the loop never
+            // reads, so a whole header with a bad length is corruption.
+            if crc32fast::hash(len_bytes) != len_checksum {
+                bail!("Journal length checksum mismatch at byte {}", position);
+            }
+            let len = u32::from_le_bytes(len_bytes.try_into()?) as usize;
+            let start = position + HEADER_LEN;
+            if raw.len() - start < len {
+                break;
+            }
+            let payload = &raw[start..start + len];
+            if crc32fast::hash(payload) != checksum {
+                if start + len == raw.len() {
+                    break;
+                }
+                bail!("Journal checksum mismatch at byte {}", position);
+            }
+            position = start + len;
+            entries.push((serde_json::from_slice::<JournalEntry>(payload)?, position));
+        }
+        Ok((entries, position))
+    }
+}
+
+// Rebuilds engine state by feeding the journaled commands through the same
+// code path as live traffic. Records the last applied offset per partition so
+// the consumer resumes after them, and returns the last replayed sequence.
+pub fn replay(
+    entries: Vec<JournalEntry>,
+    registry: &mut EngineRegistry,
+    applied_offsets: &mut FxHashMap<i32, i64>,
+) -> Option<u64> {
+    let mut last_sequence = None;
+    for entry in entries {
+        applied_offsets.insert(entry.partition, entry.offset);
+        last_sequence = Some(entry.sequence);
+        let symbol = entry.command.symbol().clone();
+        registry.process(entry.command, entry.ingress_time);
+        // Market data for replayed commands was already published; draining it
+        // keeps the L3 sequence in step with what consumers have seen.
+        registry.depth_updates(&symbol);
+        registry.order_book_updates(&symbol);
+    }
+    last_sequence
+}
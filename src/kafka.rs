@@ -1,50 +1,183 @@
+use std::{sync::Arc, time::Duration};
+
 use anyhow::Result;
 use rdkafka::{
-    ClientConfig,
+    ClientConfig, Offset, TopicPartitionList,
     consumer::{Consumer, StreamConsumer},
     message::Message,
-    producer::{FutureProducer, FutureRecord},
+    producer::{FutureProducer, FutureRecord, Producer},
 };
+use rustc_hash::FxHashMap;
 use smallvec::SmallVec;
 use tokio::sync::mpsc;
 
-use crate::model::{EngineEvent, IncomingOrder};
+use crate::{
+    clock::{Clock, SystemClock},
+    model::{Command, EventEnvelope, MarketDataMessage, OrderBookMessage, Timestamp},
+};
 
 pub struct KafkaConsumer {
     consumer: StreamConsumer,
 }
 
+pub struct ConsumedCommand {
+    pub command: Command,
+    pub partition: i32,
+    pub offset: i64,
+    pub ingress_time: Timestamp,
+}
+
+pub struct OutputBatch {
+    events_topic: String,
+    market_data_topic: String,
+    order_book_topic: String,
+    commands_topic: String,
+    pub events: SmallVec<[EventEnvelope; 16]>,
+    pub market_data: Vec<MarketDataMessage>,
+    pub order_book: Vec<OrderBookMessage>,
+    pub commands: Vec<Command>,
+}
+
+impl OutputBatch {
+    pub fn new(
+        events_topic: &str,
+        market_data_topic: &str,
+        order_book_topic: &str,
+        commands_topic: &str,
+    ) -> Self {
+        OutputBatch {
+            events_topic: events_topic.to_string(),
+            market_data_topic: market_data_topic.to_string(),
+            order_book_topic: order_book_topic.to_string(),
+            commands_topic: commands_topic.to_string(),
+            events: SmallVec::new(),
+            market_data: Vec::new(),
+            order_book: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len() + self.market_data.len() + self.order_book.len() + self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+            && self.market_data.is_empty()
+            && self.order_book.is_empty()
+            && self.commands.is_empty()
+    }
+}
+
 pub struct KafkaProducer {
     producer: FutureProducer,
+    transaction: Option<Transaction>,
+}
+
+// Offsets are committed on behalf of `consumer`, whose group metadata changes
+// with every rebalance, so it is read afresh for each transaction.
+struct Transaction {
+    consumer_topic: String,
+    consumer: Arc<KafkaConsumer>,
 }
 
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(10);
+
 impl KafkaConsumer {
-    pub fn new(brokers: &str, group_id: &str, topic: &str) -> Result<Self> {
+    // In transactional mode offsets are never auto-committed; they are sent
+    // along with the produced events by `KafkaProducer::publish`.
+    pub fn new(brokers: &str, group_id: &str, transactional: bool) -> Result<Self> {
         let consumer: StreamConsumer = ClientConfig::new()
             .set("bootstrap.servers", brokers)
             .set("group.id", group_id)
-            .set("enable.auto.commit", "true")
+            .set("enable.auto.commit", (!transactional).to_string())
+            .set(
+                "isolation.level",
+                if transactional {
+                    "read_committed"
+                } else {
+                    "read_uncommitted"
+                },
+            )
             .set("auto.offset.reset", "earliest")
             .create()?;
 
-        consumer.subscribe(&[topic])?;
-
         Ok(KafkaConsumer { consumer })
     }
 
-    pub async fn consume_orders(&self, tx: mpsc::Sender<IncomingOrder>) -> Result<()> {
+    // With no restored offsets the consumer joins the group as before;
+    // otherwise every partition is assigned explicitly so consumption resumes
+    // right after the last command already applied to the engine.
+    pub fn start(&self, topic: &str, applied_offsets: &FxHashMap<i32, i64>) -> Result<()> {
+        if applied_offsets.is_empty() {
+            self.consumer.subscribe(&[topic])?;
+            return Ok(());
+        }
+        let mut assignment = TopicPartitionList::new();
+        for partition in self.partitions(topic)? {
+            let offset = match applied_offsets.get(&partition) {
+                Some(offset) => Offset::Offset(offset + 1),
+                None => Offset::Stored,
+            };
+            assignment.add_partition_offset(topic, partition, offset)?;
+        }
+        self.consumer.assign(&assignment)?;
+        Ok(())
+    }
+
+    // Last offset per partition whose results were committed by the group.
+    pub fn committed_offsets(&self, topic: &str) -> Result<FxHashMap<i32, i64>> {
+        let mut partitions = TopicPartitionList::new();
+        for partition in self.partitions(topic)? {
+            partitions.add_partition(topic, partition);
+        }
+        let committed = self
+            .consumer
+            .committed_offsets(partitions, TRANSACTION_TIMEOUT)?;
+        Ok(committed
+            .elements()
+            .iter()
+            .filter_map(|element| match element.offset() {
+                Offset::Offset(next) => Some((element.partition(), next - 1)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn partitions(&self, topic: &str) -> Result<Vec<i32>> {
+        let metadata = self
+            .consumer
+            .fetch_metadata(Some(topic), TRANSACTION_TIMEOUT)?;
+        Ok(metadata
+            .topics()
+            .iter()
+            .flat_map(|metadata_topic| metadata_topic.partitions())
+            .map(|partition| partition.id())
+            .collect())
+    }
+
+    pub async fn consume_commands(&self, tx: mpsc::Sender<ConsumedCommand>) -> Result<()> {
         loop {
             match self.consumer.recv().await {
                 Ok(message) => {
                     if let Some(payload) = message.payload() {
-                        match serde_json::from_slice::<IncomingOrder>(payload) {
-                            Ok(order) => {
-                                if tx.send(order).await.is_err() {
+                        match serde_json::from_slice::<Command>(payload) {
+                            Ok(command) => {
+                                let consumed = ConsumedCommand {
+                                    command,
+                                    partition: message.partition(),
+                                    offset: message.offset(),
+                                    ingress_time: message.timestamp().to_millis().map_or_else(
+                                        || SystemClock.now(),
+                                        |millis| millis * 1_000_000,
+                                    ),
+                                };
+                                if tx.send(consumed).await.is_err() {
                                     break;
                                 }
                             }
                             Err(e) => {
-                                eprintln!("Failed to deserialize order: {}", e);
+                                eprintln!("Failed to deserialize command: {}", e);
                             }
                         }
                     }
@@ -65,13 +198,101 @@ impl KafkaProducer {
             .set("acks", "all")
             .create()?;
 
-        Ok(KafkaProducer { producer })
+        Ok(KafkaProducer {
+            producer,
+            transaction: None,
+        })
+    }
+
+    pub fn transactional(
+        brokers: &str,
+        transactional_id: &str,
+        consumer_topic: &str,
+        consumer: Arc<KafkaConsumer>,
+    ) -> Result<Self> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("acks", "all")
+            .set("enable.idempotence", "true")
+            .set("transactional.id", transactional_id)
+            .create()?;
+        producer.init_transactions(TRANSACTION_TIMEOUT)?;
+
+        Ok(KafkaProducer {
+            producer,
+            transaction: Some(Transaction {
+                consumer_topic: consumer_topic.to_string(),
+                consumer,
+            }),
+        })
+    }
+
+    pub fn is_transactional(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    // Sends a batch of events. In transactional mode the batch and the
+    // consumer offsets it was produced from are committed atomically, and a
+    // failure aborts both.
+    pub async fn publish(
+        &self,
+        batch: &mut OutputBatch,
+        applied_offsets: &FxHashMap<i32, i64>,
+    ) -> Result<()> {
+        let events: SmallVec<[EventEnvelope; 16]> = batch.events.drain(..).collect();
+        let market_data = std::mem::take(&mut batch.market_data);
+        let order_book = std::mem::take(&mut batch.order_book);
+        let commands = std::mem::take(&mut batch.commands);
+        let Some(transaction) = &self.transaction else {
+            self.send_events(&batch.events_topic, events).await?;
+            self.send_market_data(&batch.market_data_topic, market_data)
+                .await?;
+            self.send_order_book(&batch.order_book_topic, order_book)
+                .await?;
+            return self.send_commands(&batch.commands_topic, commands).await;
+        };
+
+        self.producer.begin_transaction()?;
+        let result = async {
+            self.send_events(&batch.events_topic, events).await?;
+            self.send_market_data(&batch.market_data_topic, market_data)
+                .await?;
+            self.send_order_book(&batch.order_book_topic, order_book)
+                .await?;
+            self.send_commands(&batch.commands_topic, commands).await?;
+            let mut offsets = TopicPartitionList::new();
+            for (partition, offset) in applied_offsets {
+                offsets.add_partition_offset(
+                    &transaction.consumer_topic,
+                    *partition,
+                    Offset::Offset(offset + 1),
+                )?;
+            }
+            let group_metadata = transaction
+                .consumer
+                .consumer
+                .group_metadata()
+                .ok_or_else(|| anyhow::anyhow!("Consumer has no group metadata"))?;
+            self.producer.send_offsets_to_transaction(
+                &offsets,
+                &group_metadata,
+                TRANSACTION_TIMEOUT,
+            )?;
+            self.producer.commit_transaction(TRANSACTION_TIMEOUT)?;
+            Ok(())
+        }
+        .await;
+
+        if result.is_err() {
+            self.producer.abort_transaction(TRANSACTION_TIMEOUT)?;
+        }
+        result
     }
 
     pub async fn send_events(
         &self,
         topic: &str,
-        events: SmallVec<[EngineEvent; 16]>,
+        events: SmallVec<[EventEnvelope; 16]>,
     ) -> Result<()> {
         if events.is_empty() {
             return Ok(());
@@ -79,7 +300,7 @@ impl KafkaProducer {
 
         for event in events {
             let payload = serde_json::to_vec(&event)?;
-            let record = FutureRecord::to(topic).key("").payload(&payload);
+            let record = FutureRecord::to(topic).key(&event.symbol).payload(&payload);
 
             self.producer
                 .send(record, None)
@@ -89,4 +310,62 @@ impl KafkaProducer {
 
         Ok(())
     }
+
+    pub async fn send_market_data(
+        &self,
+        topic: &str,
+        messages: Vec<MarketDataMessage>,
+    ) -> Result<()> {
+        for message in messages {
+            let payload = serde_json::to_vec(&message)?;
+            let record = FutureRecord::to(topic)
+                .key(message.symbol())
+                .payload(&payload);
+
+            self.producer
+                .send(record, None)
+                .await
+                .map_err(|(e, _)| anyhow::anyhow!("Failed to send market data to Kafka: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    // Messages of a symbol share a key, and so a partition, which keeps their
+    // sequence numbers in order on the topic.
+    pub async fn send_order_book(
+        &self,
+        topic: &str,
+        messages: Vec<OrderBookMessage>,
+    ) -> Result<()> {
+        for message in messages {
+            let payload = serde_json::to_vec(&message)?;
+            let record = FutureRecord::to(topic)
+                .key(message.symbol())
+                .payload(&payload);
+
+            self.producer
+                .send(record, None)
+                .await
+                .map_err(|(e, _)| anyhow::anyhow!("Failed to send order book to Kafka: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn send_commands(&self, topic: &str, commands: Vec<Command>) -> Result<()> {
+        for command in commands {
+            let payload = serde_json::to_vec(&command)?;
+            let record = FutureRecord::to(topic)
+                .key(command.symbol())
+                .payload(&payload);
+
+            self.producer
+                .send(record, None)
+                .await
+                .map_err(|(e, _)| anyhow::anyhow!("Failed to send command to Kafka: {}", e))?;
+        }
+
+        Ok(())
+    }
 }
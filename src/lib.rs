@@ -0,0 +1,12 @@
+pub mod clock;
+pub mod config;
+pub mod engine;
+pub mod journal;
+pub mod kafka;
+pub mod logger;
+pub mod matcher;
+pub mod model;
+pub mod policies;
+pub mod registry;
+pub mod snapshot;
+pub mod storage;
@@ -1,22 +1,53 @@
-use crate::model::{EngineEvent, IncomingOrder};
+use crate::model::{Command, EngineEvent, EventEnvelope, PRICE_SCALE};
 
 pub struct Log;
 
 impl Log {
-    pub fn order(count: u64, order: &IncomingOrder) {
-        println!("[ORDER #{}] {:?} {} @ {:?}", 
-            count, order.side, order.amount as f64 / 1_000_000.0, order.order_type);
+    pub fn command(count: u64, command: &Command) {
+        match command {
+            Command::NewOrder(order) => {
+                println!("[ORDER #{}] {} {:?} {} @ {:?}", 
+                    count, order.symbol, order.side, order.amount as f64 / 1_000_000.0, order.order_type);
+            }
+            Command::CancelOrder { symbol, order_id, .. } => {
+                println!("[CANCEL #{}] {} order {}", count, symbol, order_id);
+            }
+            Command::CancelAllOrders { symbol, user_id } => {
+                println!("[CANCEL_ALL #{}] {} user {}", count, symbol, user_id);
+            }
+            Command::AmendOrder { symbol, order_id, price, amount, .. } => {
+                println!("[AMEND #{}] {} order {} price {:?} amount {:?}", count, symbol, order_id, price, amount);
+            }
+            Command::Halt { symbol } => println!("[HALT #{}] {}", count, symbol),
+            Command::Resume { symbol } => println!("[RESUME #{}] {}", count, symbol),
+            Command::StartAuction { symbol } => println!("[AUCTION #{}] {}", count, symbol),
+            Command::SetTradingStatus { symbol, status } => {
+                println!("[STATUS #{}] {} {:?}", count, symbol, status);
+            }
+            Command::Tick { symbol } => println!("[TICK #{}] {}", count, symbol),
+        }
+    }
+
+    pub fn restore(engines: usize, journal_sequence: Option<u64>) {
+        println!("[RESTORE] {} order books from snapshot (journal sequence: {:?})", engines, journal_sequence);
     }
 
-    pub fn events(events: &[EngineEvent]) {
-        for event in events {
-            match event {
+    pub fn replay(count: usize) {
+        println!("[REPLAY] {} journaled commands", count);
+    }
+
+    pub fn events(events: &[EventEnvelope]) {
+        for envelope in events {
+            match &envelope.event {
                 EngineEvent::TradeExecuted(trade) => {
                     println!("  → TRADE: {} tokens @ ${:.4} (trade_id: {})", 
                         trade.amount as f64 / 1_000_000.0,
-                        trade.price.mantissa() as f64 / 10_f64.powi(trade.price.scale() as i32),
+                        trade.price.units() as f64 / PRICE_SCALE as f64,
                         trade.trade_id);
                 }
+                EngineEvent::OrderAccepted { order_id, .. } => {
+                    println!("  → ORDER_ACCEPTED: {}", order_id);
+                }
                 EngineEvent::OrderPlaced { .. } => println!("  → ORDER_PLACED"),
                 EngineEvent::OrderCancelled { order_id, reason, .. } => {
                     println!("  → ORDER_CANCELLED: {} ({})", order_id, reason);
@@ -24,6 +55,28 @@ impl Log {
                 EngineEvent::OrderRejected { order_id, reason } => {
                     println!("  → ORDER_REJECTED: {} ({})", order_id, reason);
                 }
+                EngineEvent::StopOrderPlaced { order_id, stop_price, .. } => {
+                    println!("  → STOP_PLACED: {} (stop: {})", order_id, stop_price);
+                }
+                EngineEvent::StopTriggered { order_id, trade_price, .. } => {
+                    println!("  → STOP_TRIGGERED: {} (at: {})", order_id, trade_price);
+                }
+                EngineEvent::OrderAmended { order_id, .. } => {
+                    println!("  → ORDER_AMENDED: {}", order_id);
+                }
+                EngineEvent::TradingStatusChanged { status, reason } => {
+                    println!("  → TRADING_STATUS: {:?} ({:?})", status, reason);
+                }
+                EngineEvent::IndicativeUncross { indicative: Some(auction) } => {
+                    println!("  → INDICATIVE: {} @ {}", auction.volume as f64 / 1_000_000.0, auction.price);
+                }
+                EngineEvent::IndicativeUncross { indicative: None } => println!("  → INDICATIVE: none"),
+                EngineEvent::ExecutionReport(report) => {
+                    println!("  → EXEC_REPORT: {} {:?} (filled: {}, remaining: {})",
+                        report.order_id, report.status,
+                        report.filled_amount as f64 / 1_000_000.0,
+                        report.remaining_amount as f64 / 1_000_000.0);
+                }
             }
         }
     }
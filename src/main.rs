@@ -1,19 +1,15 @@
-mod engine;
-mod kafka;
-mod logger;
-mod matcher;
-mod model;
-mod policies;
-mod storage;
+use std::sync::Arc;
 
 use anyhow::Result;
-use smallvec::SmallVec;
+use rustc_hash::FxHashMap;
 use tokio::sync::mpsc;
 
-use crate::engine::MatchEngine;
-use crate::kafka::{KafkaConsumer, KafkaProducer};
-use crate::logger::Log;
-use crate::model::{EngineEvent, IncomingOrder};
+use match_engine::config::load_instruments;
+use match_engine::journal::{self, Journal};
+use match_engine::kafka::{ConsumedCommand, KafkaConsumer, KafkaProducer, OutputBatch};
+use match_engine::logger::Log;
+use match_engine::registry::EngineRegistry;
+use match_engine::snapshot::{Snapshot, SnapshotStore};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -23,47 +19,222 @@ async fn main() -> Result<()> {
         std::env::var("KAFKA_CONSUMER_TOPIC").unwrap_or_else(|_| "orders".to_string());
     let producer_topic =
         std::env::var("KAFKA_PRODUCER_TOPIC").unwrap_or_else(|_| "trades".to_string());
+    let market_data_topic =
+        std::env::var("KAFKA_MARKET_DATA_TOPIC").unwrap_or_else(|_| "market-data".to_string());
+    let market_data_depth: usize = std::env::var("MARKET_DATA_DEPTH")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(10);
+    let market_data_interval_ms: u64 = std::env::var("MARKET_DATA_SNAPSHOT_INTERVAL_MS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1_000);
+    let order_book_topic =
+        std::env::var("KAFKA_ORDER_BOOK_TOPIC").unwrap_or_else(|_| "order-book".to_string());
+    let order_book_interval_ms: u64 = std::env::var("ORDER_BOOK_SNAPSHOT_INTERVAL_MS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(10_000);
+    let instruments_config =
+        std::env::var("INSTRUMENTS_CONFIG").unwrap_or_else(|_| "instruments.json".to_string());
 
-    let (tx, mut rx) = mpsc::channel::<IncomingOrder>(1000);
+    let journal_path = std::env::var("JOURNAL_PATH").ok();
+    let redis_url = std::env::var("REDIS_URL").ok();
+    let snapshot_key =
+        std::env::var("SNAPSHOT_KEY").unwrap_or_else(|_| "match-engine:snapshot".to_string());
+    let snapshot_interval_ms: u64 = std::env::var("SNAPSHOT_INTERVAL_MS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(60_000);
 
-    let kafka_consumer = KafkaConsumer::new(&brokers, &group_id, &consumer_topic)?;
-    let kafka_producer = KafkaProducer::new(&brokers)?;
+    let transactional_id = std::env::var("KAFKA_TRANSACTIONAL_ID").ok();
 
-    let consumer_handle = tokio::spawn(async move { kafka_consumer.consume_orders(tx).await });
+    let instruments = load_instruments(&instruments_config)?;
+    let mut registry = EngineRegistry::new(&instruments);
 
-    let mut match_engine = MatchEngine::new();
+    let kafka_consumer = Arc::new(KafkaConsumer::new(
+        &brokers,
+        &group_id,
+        transactional_id.is_some(),
+    )?);
+    // In transactional mode the group's committed offsets are the source of
+    // truth: nothing past them was ever published.
+    let committed_offsets = match transactional_id {
+        Some(_) => Some(kafka_consumer.committed_offsets(&consumer_topic)?),
+        None => None,
+    };
+
+    let mut applied_offsets: FxHashMap<i32, i64> = FxHashMap::default();
+    let mut journal_sequence: Option<u64> = None;
+
+    let mut snapshot_store = match redis_url {
+        Some(url) => Some(SnapshotStore::new(&url, &snapshot_key).await?),
+        None => None,
+    };
+    if let Some(store) = snapshot_store.as_mut()
+        && let Some(snapshot) = store.load().await?
+    {
+        Log::restore(snapshot.engines.len(), snapshot.journal_sequence);
+        registry.restore(snapshot.engines)?;
+        applied_offsets = snapshot.offsets;
+        journal_sequence = snapshot.journal_sequence;
+    }
+
+    let mut journal = match journal_path {
+        Some(path) => {
+            let (mut journal, entries) = Journal::open(&path)?;
+            let entries: Vec<_> = entries
+                .into_iter()
+                .filter(|entry| journal_sequence.is_none_or(|sequence| entry.sequence > sequence))
+                .take_while(|entry| {
+                    committed_offsets.as_ref().is_none_or(|committed| {
+                        committed
+                            .get(&entry.partition)
+                            .is_some_and(|committed| entry.offset <= *committed)
+                    })
+                })
+                .collect();
+            Log::replay(entries.len());
+            if let Some(sequence) = journal::replay(entries, &mut registry, &mut applied_offsets) {
+                journal_sequence = Some(sequence);
+            }
+            if committed_offsets.is_some() {
+                journal.truncate_after(journal_sequence)?;
+            }
+            Some(journal)
+        }
+        None => None,
+    };
+
+    let (tx, mut rx) = mpsc::channel::<ConsumedCommand>(1000);
+
+    kafka_consumer.start(&consumer_topic, &applied_offsets)?;
+    let kafka_producer = match &transactional_id {
+        Some(transactional_id) => KafkaProducer::transactional(
+            &brokers,
+            transactional_id,
+            &consumer_topic,
+            Arc::clone(&kafka_consumer),
+        )?,
+        None => KafkaProducer::new(&brokers)?,
+    };
+
+    let consumer_handle = tokio::spawn(async move { kafka_consumer.consume_commands(tx).await });
 
     let engine_handle = tokio::spawn(async move {
-        let mut events_batch: SmallVec<[EngineEvent; 16]> = SmallVec::new();
+        let mut batch = OutputBatch::new(
+            &producer_topic,
+            &market_data_topic,
+            &order_book_topic,
+            &consumer_topic,
+        );
+        let mut unpublished_commands = 0;
         let batch_size = 16;
         let flush_interval = tokio::time::Duration::from_millis(100);
         let mut flush_timer = tokio::time::interval(flush_interval);
-        let mut order_count = 0;
+        let snapshot_interval = tokio::time::Duration::from_millis(snapshot_interval_ms);
+        let mut snapshot_timer = tokio::time::interval(snapshot_interval);
+        let market_data_interval = tokio::time::Duration::from_millis(market_data_interval_ms);
+        let mut market_data_timer = tokio::time::interval(market_data_interval);
+        let order_book_interval = tokio::time::Duration::from_millis(order_book_interval_ms);
+        let mut order_book_timer = tokio::time::interval(order_book_interval);
+        let mut session_timer = tokio::time::interval(tokio::time::Duration::from_secs(1));
+        let mut command_count = 0;
 
         loop {
-            tokio::select! {
-                Some(order) = rx.recv() => {
-                    order_count += 1;
-                    Log::order(order_count, &order);
-                    
-                    let events = match_engine.process(order);
-                    Log::events(&events);
-                    
-                    events_batch.extend(events);
+            let flush = tokio::select! {
+                Some(consumed) = rx.recv() => {
+                    command_count += 1;
+                    Log::command(command_count, &consumed.command);
 
-                    if events_batch.len() >= batch_size {
-                        if let Err(e) = kafka_producer.send_events(&producer_topic, events_batch.drain(..).collect()).await {
-                            eprintln!("Failed to send events batch: {}", e);
+                    if let Some(journal) = journal.as_mut() {
+                        let appended = journal.append(
+                            consumed.partition,
+                            consumed.offset,
+                            consumed.ingress_time,
+                            &consumed.command,
+                        );
+                        match appended {
+                            Ok(sequence) => journal_sequence = Some(sequence),
+                            Err(e) => {
+                                eprintln!("Failed to append to journal: {}", e);
+                                break;
+                            }
                         }
                     }
+                    applied_offsets.insert(consumed.partition, consumed.offset);
+
+                    // Commands restored from a snapshot but committed after
+                    // it are re-processed to catch up, without re-publishing.
+                    let catching_up = committed_offsets
+                        .as_ref()
+                        .and_then(|committed| committed.get(&consumed.partition))
+                        .is_some_and(|committed| consumed.offset <= *committed);
+                    let symbol = consumed.command.symbol().clone();
+                    let events = registry.process(consumed.command, consumed.ingress_time);
+                    Log::events(&events);
+                    let depth_updates = registry.depth_updates(&symbol);
+                    let order_book_updates = registry.order_book_updates(&symbol);
+
+                    if !catching_up {
+                        batch.events.extend(events);
+                        batch.market_data.extend(depth_updates);
+                        batch.order_book.extend(order_book_updates);
+                    }
+                    unpublished_commands += 1;
+
+                    batch.len() >= batch_size
+                }
+                _ = flush_timer.tick() => unpublished_commands > 0 || !batch.is_empty(),
+                _ = market_data_timer.tick() => {
+                    batch.market_data.extend(registry.depth_snapshots(market_data_depth));
+                    false
                 }
-                _ = flush_timer.tick() => {
-                    if !events_batch.is_empty() {
-                        if let Err(e) = kafka_producer.send_events(&producer_topic, events_batch.drain(..).collect()).await {
+                _ = order_book_timer.tick() => {
+                    batch.order_book.extend(registry.order_book_snapshots());
+                    false
+                }
+                _ = session_timer.tick() => {
+                    batch.commands.extend(registry.due_ticks());
+                    !batch.commands.is_empty()
+                }
+                _ = snapshot_timer.tick(), if snapshot_store.is_some() => {
+                    // Events of commands covered by the snapshot must be out
+                    // before it is saved, or a restore would never publish them.
+                    if unpublished_commands > 0 || !batch.is_empty() {
+                        if let Err(e) = kafka_producer.publish(&mut batch, &applied_offsets).await {
                             eprintln!("Failed to send events batch: {}", e);
+                            if kafka_producer.is_transactional() {
+                                break;
+                            }
+                            continue;
                         }
+                        unpublished_commands = 0;
+                    }
+                    let snapshot = Snapshot {
+                        engines: registry.snapshot(),
+                        offsets: applied_offsets.clone(),
+                        journal_sequence,
+                    };
+                    if let Some(store) = snapshot_store.as_mut()
+                        && let Err(e) = store.save(&snapshot).await {
+                        eprintln!("Failed to save snapshot: {}", e);
+                    }
+                    false
+                }
+            };
+
+            // In transactional mode the loop does not move on to the next
+            // command until the batch and its offsets are committed; a failed
+            // commit stops the engine so it restarts from committed state.
+            if flush {
+                if let Err(e) = kafka_producer.publish(&mut batch, &applied_offsets).await {
+                    eprintln!("Failed to send events batch: {}", e);
+                    if kafka_producer.is_transactional() {
+                        break;
                     }
                 }
+                unpublished_commands = 0;
             }
         }
     });
@@ -1,9 +1,12 @@
-use std::cmp::min;
+use std::{cmp::min, collections::VecDeque};
 
 use smallvec::SmallVec;
 
 use crate::{
-    model::{BookOrder, OrderSide, ProcessOrder, SelfTradeAction, Trade, TradeId},
+    model::{
+        Amount, BookOrder, CancelReason, EngineEvent, OrderId, OrderSide, Price, ProcessOrder,
+        SelfTradeAction, Trade, TradeId,
+    },
     policies::PolicyChecker,
     storage::{BookSide, PriceKey},
 };
@@ -15,12 +18,22 @@ impl Matcher {
         aggressor: &mut ProcessOrder,
         book: &mut BookSide<K>,
         next_trade_id: &mut TradeId,
-    ) -> SmallVec<[Trade; 16]> {
-        let mut executed_trades: SmallVec<[Trade; 16]> = SmallVec::new();
+        self_trade_action: SelfTradeAction,
+        band_limit: Option<Price>,
+    ) -> SmallVec<[EngineEvent; 16]> {
+        let mut executed_events: SmallVec<[EngineEvent; 16]> = SmallVec::new();
 
-        let filter = |order: &BookOrder| {
-            let self_trade_action =
-                PolicyChecker::check_self_trade(aggressor.user_id, order.user_id);
+        // All-or-none makers larger than what's left of the aggressor are
+        // passed over like skipped self-trades.
+        let filter = |order: &BookOrder, remaining: Amount| {
+            if order.all_or_none && order.total_amount() > remaining {
+                return false;
+            }
+            let self_trade_action = PolicyChecker::check_self_trade(
+                self_trade_action,
+                aggressor.user_id,
+                order.user_id,
+            );
             match self_trade_action {
                 SelfTradeAction::Skip => false,
                 SelfTradeAction::Allow => true,
@@ -28,27 +41,73 @@ impl Matcher {
             }
         };
 
+        // Walks the book in priority order from a cursor rather than from the
+        // top each time, so skipped makers are only ever visited once.
+        let mut cursor = book.best_order().map(|order| order.order_id);
         while aggressor.amount > 0 {
-            let maker_order_ref = match book.peek_best(filter) {
-                Some(o) => o,
-                None => break,
+            let remaining = aggressor.amount;
+            let Some(maker_order) = cursor
+                .and_then(|order_id| book.peek_from(order_id, |order| filter(order, remaining)))
+                .cloned()
+            else {
+                break;
             };
 
             let is_match = PolicyChecker::check_price_match(
                 aggressor.side,
-                maker_order_ref.price,
+                maker_order.price,
                 aggressor.price,
                 aggressor.is_market,
             );
 
-            if !is_match {
+            if !is_match
+                || PolicyChecker::is_outside_band(aggressor.side, maker_order.price, band_limit)
+            {
                 break;
             }
 
-            let mut maker_order = match book.pop_best(filter) {
-                Some(o) => o,
-                None => break,
-            };
+            let next = book
+                .next_order(maker_order.order_id)
+                .map(|order| (order.order_id, order.price));
+            cursor = next.map(|(order_id, _)| order_id);
+
+            match PolicyChecker::check_self_trade(
+                self_trade_action,
+                aggressor.user_id,
+                maker_order.user_id,
+            ) {
+                SelfTradeAction::CancelMaker => {
+                    book.remove(maker_order.order_id);
+                    executed_events.push(Self::self_trade_cancel(
+                        maker_order.order_id,
+                        maker_order.total_amount(),
+                    ));
+                    continue;
+                }
+                SelfTradeAction::CancelTaker => {
+                    executed_events.push(Self::self_trade_cancel(
+                        aggressor.order_id,
+                        aggressor.amount,
+                    ));
+                    aggressor.amount = 0;
+                    break;
+                }
+                SelfTradeAction::CancelBoth => {
+                    book.remove(maker_order.order_id);
+                    executed_events.push(Self::self_trade_cancel(
+                        maker_order.order_id,
+                        maker_order.total_amount(),
+                    ));
+                    executed_events.push(Self::self_trade_cancel(
+                        aggressor.order_id,
+                        aggressor.amount,
+                    ));
+                    aggressor.amount = 0;
+                    break;
+                }
+                SelfTradeAction::Allow | SelfTradeAction::Skip => (),
+            }
+
             let trade_amount = min(aggressor.amount, maker_order.amount);
 
             let trade = Trade {
@@ -68,15 +127,137 @@ impl Matcher {
             };
             *next_trade_id += 1;
 
-            executed_trades.push(trade);
+            executed_events.push(EngineEvent::TradeExecuted(trade));
 
             aggressor.amount -= trade_amount;
-            maker_order.amount -= trade_amount;
 
-            if maker_order.amount > 0 {
-                book.insert(maker_order);
+            book.fill(maker_order.order_id, trade_amount);
+
+            // A refreshed iceberg tranche rejoins the back of its level, so it
+            // is next unless orders behind it at that price are still to come.
+            let same_level = next.is_some_and(|(_, price)| price == maker_order.price);
+            if !same_level && book.get(maker_order.order_id).is_some() {
+                cursor = Some(maker_order.order_id);
             }
         }
-        executed_trades
+        executed_events
+    }
+
+    // Dry run of `hard_match`: how much of the aggressor would execute against
+    // the same makers under the same price, band, all-or-none and self-trade
+    // rules. Each level is replayed tranche by tranche, with refreshed iceberg
+    // tranches queued behind the orders still to come at that price, so the
+    // result matches the real match exactly.
+    pub fn fillable_amount<K: PriceKey>(
+        aggressor: &ProcessOrder,
+        book: &BookSide<K>,
+        self_trade_action: SelfTradeAction,
+        band_limit: Option<Price>,
+    ) -> Amount {
+        let mut remaining = aggressor.amount;
+        let mut cursor = book.best_order();
+        let mut refreshed: VecDeque<(&BookOrder, Amount, Amount)> = VecDeque::new();
+        while remaining > 0
+            && let Some(first) = cursor
+        {
+            let is_match = PolicyChecker::check_price_match(
+                aggressor.side,
+                first.price,
+                aggressor.price,
+                aggressor.is_market,
+            );
+            if !is_match || PolicyChecker::is_outside_band(aggressor.side, first.price, band_limit)
+            {
+                break;
+            }
+            refreshed.clear();
+            while remaining > 0 {
+                let (maker_order, amount, hidden_amount) = match cursor {
+                    Some(order) if order.price == first.price => {
+                        cursor = book.next_order(order.order_id);
+                        (order, order.amount, order.hidden_amount)
+                    }
+                    _ => match refreshed.pop_front() {
+                        Some(tranche) => tranche,
+                        None => break,
+                    },
+                };
+                if maker_order.all_or_none && amount + hidden_amount > remaining {
+                    continue;
+                }
+                match PolicyChecker::check_self_trade(
+                    self_trade_action,
+                    aggressor.user_id,
+                    maker_order.user_id,
+                ) {
+                    SelfTradeAction::CancelTaker | SelfTradeAction::CancelBoth => {
+                        return aggressor.amount - remaining;
+                    }
+                    SelfTradeAction::Skip | SelfTradeAction::CancelMaker => continue,
+                    SelfTradeAction::Allow => (),
+                }
+                let trade_amount = min(remaining, amount);
+                remaining -= trade_amount;
+                if trade_amount == amount && hidden_amount > 0 {
+                    let display_amount = maker_order
+                        .display_amount
+                        .unwrap_or(hidden_amount)
+                        .min(hidden_amount);
+                    refreshed.push_back((
+                        maker_order,
+                        display_amount,
+                        hidden_amount - display_amount,
+                    ));
+                }
+            }
+        }
+        aggressor.amount - remaining
+    }
+
+    // Executes `volume` between the crossing bids and asks in priority order,
+    // every trade at the single auction price. Neither side aggressed, so the
+    // bid is reported as the taker, and self-trade prevention does not apply.
+    // All-or-none orders sit out.
+    pub fn uncross<B: PriceKey, A: PriceKey>(
+        bids: &mut BookSide<B>,
+        asks: &mut BookSide<A>,
+        price: Price,
+        mut volume: Amount,
+        next_trade_id: &mut TradeId,
+    ) -> SmallVec<[EngineEvent; 16]> {
+        let mut executed_events: SmallVec<[EngineEvent; 16]> = SmallVec::new();
+        while volume > 0 {
+            let (Some(bid), Some(ask)) = (
+                bids.peek_best(|order| !order.all_or_none).cloned(),
+                asks.peek_best(|order| !order.all_or_none).cloned(),
+            ) else {
+                break;
+            };
+            let trade_amount = min(volume, min(bid.amount, ask.amount));
+
+            executed_events.push(EngineEvent::TradeExecuted(Trade {
+                trade_id: *next_trade_id,
+                maker_order_id: ask.order_id,
+                taker_order_id: bid.order_id,
+                amount: trade_amount,
+                buyer_id: bid.user_id,
+                seller_id: ask.user_id,
+                price,
+            }));
+            *next_trade_id += 1;
+
+            volume -= trade_amount;
+            bids.fill(bid.order_id, trade_amount);
+            asks.fill(ask.order_id, trade_amount);
+        }
+        executed_events
+    }
+
+    fn self_trade_cancel(order_id: OrderId, remaining_amount: Amount) -> EngineEvent {
+        EngineEvent::OrderCancelled {
+            order_id,
+            remaining_amount,
+            reason: CancelReason::SelfTradePrevention,
+        }
     }
 }
@@ -1,11 +1,146 @@
-use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use uuid::Uuid;
 
-pub type Price = Decimal;
 pub type OrderId = u64;
 pub type TradeId = u64;
 pub type Amount = u64;
+pub type Symbol = String;
+
+// Amounts are integers in millionths of a unit.
+pub const AMOUNT_SCALE: Amount = 1_000_000;
+
+pub const PRICE_DECIMALS: u32 = 8;
+pub const PRICE_SCALE: i64 = 100_000_000;
+
+// Fixed-point price. On the wire it is a decimal held in units of
+// 10^-PRICE_DECIMALS, and a price with more decimals than that fails to
+// deserialize rather than being rounded onto a different price. Inside an
+// engine it counts whole ticks of the instrument instead; see `TickSize`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Price(i64);
+
+impl Price {
+    pub const ZERO: Price = Price(0);
+
+    pub const fn from_units(units: i64) -> Self {
+        Price(units)
+    }
+
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    pub fn from_decimal(value: Decimal) -> Option<Self> {
+        let units = value.checked_mul(Decimal::from(PRICE_SCALE))?;
+        if !units.fract().is_zero() {
+            return None;
+        }
+        units.to_i64().map(Price)
+    }
+
+    pub fn to_decimal(self) -> Decimal {
+        Decimal::new(self.0, PRICE_DECIMALS).normalize()
+    }
+}
+
+impl From<i64> for Price {
+    fn from(value: i64) -> Self {
+        Price(value * PRICE_SCALE)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_decimal().fmt(f)
+    }
+}
+
+impl Serialize for Price {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Serialize::serialize(&self.to_decimal(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = <Decimal as Deserialize>::deserialize(deserializer)?;
+        Price::from_decimal(value).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "price {} has more than {} decimals or is out of range",
+                value, PRICE_DECIMALS
+            ))
+        })
+    }
+}
+
+// An instrument's tick size in wire units. Engines convert every price that
+// crosses their public methods, so books are keyed by tick counts; an
+// instrument without a configured tick size ticks in single units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickSize(i64);
+
+impl TickSize {
+    pub fn new(tick_size: Option<Price>) -> Self {
+        TickSize(tick_size.map_or(1, Price::units))
+    }
+
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    // `None` if any price in `value` is off the tick.
+    pub fn to_ticks<T: Prices>(self, mut value: T) -> Option<T> {
+        if self.0 == 1 {
+            return Some(value);
+        }
+        let mut on_tick = true;
+        value.for_each_price(&mut |price| {
+            on_tick &= price.0 % self.0 == 0;
+            price.0 /= self.0;
+        });
+        on_tick.then_some(value)
+    }
+
+    pub fn to_wire<T: Prices>(self, mut value: T) -> T {
+        if self.0 != 1 {
+            value.for_each_price(&mut |price| price.0 *= self.0);
+        }
+        value
+    }
+}
+
+// Anything carrying prices across an engine's boundary.
+pub trait Prices {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price));
+}
+
+impl Prices for Price {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        f(self)
+    }
+}
+
+impl<T: Prices> Prices for Option<T> {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        if let Some(value) = self {
+            value.for_each_price(f);
+        }
+    }
+}
+
+impl<T: Prices> Prices for Vec<T> {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        for value in self {
+            value.for_each_price(f);
+        }
+    }
+}
+
+// Nanoseconds since the Unix epoch.
+pub type Timestamp = i64;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum OrderSide {
@@ -13,38 +148,76 @@ pub enum OrderSide {
     Sell,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum TimeInForce {
-    GTC, // Good Till Cancelled
-    IOC, // Immediate Or Cancel
-    FOK, // Fill Or Kill
+    GTC,            // Good Till Cancelled
+    IOC,            // Immediate Or Cancel
+    FOK,            // Fill Or Kill
+    GTD(Timestamp), // Good Till Date, expiring at the given event time
+    DAY,            // Expires at the next scheduled close, or midnight UTC without one
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub enum SelfTradeAction {
     CancelBoth,
     CancelMaker,
     CancelTaker,
     Allow,
+    #[default]
     Skip,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OrderType {
-    Market,
+    // Without `market_to_limit` only IOC and FOK are accepted. With it, the
+    // unfilled remainder rests as a limit order at the order's last fill
+    // price, for as long as `tif` says.
+    Market {
+        #[serde(default = "market_tif")]
+        tif: TimeInForce,
+        #[serde(default)]
+        market_to_limit: bool,
+    },
+    // `min_qty` makes an IOC order execute only if at least that much is
+    // available. An `all_or_none` order rests until a single counterparty
+    // can take all of it, and never trades in part.
     Limit {
         post_only: bool,
         price: Price,
         tif: TimeInForce,
+        #[serde(default)]
+        display_amount: Option<Amount>,
+        #[serde(default)]
+        min_qty: Option<Amount>,
+        #[serde(default)]
+        all_or_none: bool,
+    },
+    StopMarket {
+        stop_price: Price,
+    },
+    StopLimit {
+        stop_price: Price,
+        post_only: bool,
+        price: Price,
+        tif: TimeInForce,
     },
 }
 
+fn market_tif() -> TimeInForce {
+    TimeInForce::IOC
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CancelReason {
     UserRequest,
     IocExpired,
     FokLiquidityShortage,
+    SelfTradePrevention,
+    PriceBand,
+    Expired,
+    NoLiquidity,
+    MinQuantityShortage,
 }
 
 impl std::fmt::Display for CancelReason {
@@ -53,16 +226,59 @@ impl std::fmt::Display for CancelReason {
             CancelReason::UserRequest => write!(f, "UserRequest"),
             CancelReason::IocExpired => write!(f, "IocExpired"),
             CancelReason::FokLiquidityShortage => write!(f, "FokLiquidityShortage"),
+            CancelReason::SelfTradePrevention => write!(f, "SelfTradePrevention"),
+            CancelReason::PriceBand => write!(f, "PriceBand"),
+            CancelReason::Expired => write!(f, "Expired"),
+            CancelReason::NoLiquidity => write!(f, "NoLiquidity"),
+            CancelReason::MinQuantityShortage => write!(f, "MinQuantityShortage"),
         }
     }
 }
 
+// PreOpen and Auction both collect limit orders without matching; only the
+// auction publishes an indicative price. Leaving either uncrosses the book.
+// Closed accepts cancels only.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TradingStatus {
+    PreOpen,
+    #[default]
+    Continuous,
+    Halted,
+    Auction,
+    Closed,
+}
+
+impl TradingStatus {
+    pub fn collects_orders(&self) -> bool {
+        matches!(self, TradingStatus::PreOpen | TradingStatus::Auction)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum StatusReason {
+    Manual,
+    VolatilityBreaker,
+    Schedule,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RejectReason {
     PostOnlyViolation,
     InvalidPrice,
     InvalidAmount,
     SymbolNotFound,
+    OrderNotFound,
+    NotOrderOwner,
+    BelowMinNotional,
+    PriceBandViolation,
+    TradingHalted,
+    NotAllowedInSession,
+    MarketClosed,
+    InvalidExpiry,
+    InvalidTimeInForce,
+    InvalidMinQuantity,
+    InvalidAllOrNone,
+    DuplicateOrderId,
 }
 
 impl std::fmt::Display for RejectReason {
@@ -72,18 +288,128 @@ impl std::fmt::Display for RejectReason {
             RejectReason::InvalidPrice => write!(f, "InvalidPrice"),
             RejectReason::InvalidAmount => write!(f, "InvalidAmount"),
             RejectReason::SymbolNotFound => write!(f, "SymbolNotFound"),
+            RejectReason::OrderNotFound => write!(f, "OrderNotFound"),
+            RejectReason::NotOrderOwner => write!(f, "NotOrderOwner"),
+            RejectReason::BelowMinNotional => write!(f, "BelowMinNotional"),
+            RejectReason::PriceBandViolation => write!(f, "PriceBandViolation"),
+            RejectReason::TradingHalted => write!(f, "TradingHalted"),
+            RejectReason::NotAllowedInSession => write!(f, "NotAllowedInSession"),
+            RejectReason::MarketClosed => write!(f, "MarketClosed"),
+            RejectReason::InvalidExpiry => write!(f, "InvalidExpiry"),
+            RejectReason::InvalidTimeInForce => write!(f, "InvalidTimeInForce"),
+            RejectReason::InvalidMinQuantity => write!(f, "InvalidMinQuantity"),
+            RejectReason::InvalidAllOrNone => write!(f, "InvalidAllOrNone"),
+            RejectReason::DuplicateOrderId => write!(f, "DuplicateOrderId"),
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IncomingOrder {
+    pub symbol: Symbol,
     pub order_id: OrderId,
     pub user_id: Uuid,
     pub side: OrderSide,
     pub amount: Amount,
 
     pub order_type: OrderType,
+    #[serde(default)]
+    pub self_trade_action: Option<SelfTradeAction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Command {
+    NewOrder(IncomingOrder),
+    CancelOrder {
+        symbol: Symbol,
+        order_id: OrderId,
+        user_id: Uuid,
+    },
+    CancelAllOrders {
+        symbol: Symbol,
+        user_id: Uuid,
+    },
+    AmendOrder {
+        symbol: Symbol,
+        order_id: OrderId,
+        user_id: Uuid,
+        price: Option<Price>,
+        amount: Option<Amount>,
+    },
+    Halt {
+        symbol: Symbol,
+    },
+    Resume {
+        symbol: Symbol,
+    },
+    StartAuction {
+        symbol: Symbol,
+    },
+    SetTradingStatus {
+        symbol: Symbol,
+        status: TradingStatus,
+    },
+    // Carries no instruction of its own; its ingress time lets scheduled
+    // session transitions fire while no orders are arriving.
+    Tick {
+        symbol: Symbol,
+    },
+}
+
+impl Command {
+    pub fn symbol(&self) -> &Symbol {
+        match self {
+            Command::NewOrder(order) => &order.symbol,
+            Command::CancelOrder { symbol, .. } => symbol,
+            Command::CancelAllOrders { symbol, .. } => symbol,
+            Command::AmendOrder { symbol, .. } => symbol,
+            Command::Halt { symbol } => symbol,
+            Command::Resume { symbol } => symbol,
+            Command::StartAuction { symbol } => symbol,
+            Command::SetTradingStatus { symbol, .. } => symbol,
+            Command::Tick { symbol } => symbol,
+        }
+    }
+
+    pub fn order_id(&self) -> Option<OrderId> {
+        match self {
+            Command::NewOrder(order) => Some(order.order_id),
+            Command::CancelOrder { order_id, .. } => Some(*order_id),
+            Command::CancelAllOrders { .. } => None,
+            Command::AmendOrder { order_id, .. } => Some(*order_id),
+            Command::Halt { .. }
+            | Command::Resume { .. }
+            | Command::StartAuction { .. }
+            | Command::SetTradingStatus { .. }
+            | Command::Tick { .. } => None,
+        }
+    }
+}
+
+impl Prices for Command {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        match self {
+            Command::NewOrder(order) => match &mut order.order_type {
+                OrderType::Market { .. } => (),
+                OrderType::Limit { price, .. } => f(price),
+                OrderType::StopMarket { stop_price } => f(stop_price),
+                OrderType::StopLimit {
+                    stop_price, price, ..
+                } => {
+                    f(stop_price);
+                    f(price);
+                }
+            },
+            Command::AmendOrder { price, .. } => price.for_each_price(f),
+            Command::CancelOrder { .. }
+            | Command::CancelAllOrders { .. }
+            | Command::Halt { .. }
+            | Command::Resume { .. }
+            | Command::StartAuction { .. }
+            | Command::SetTradingStatus { .. }
+            | Command::Tick { .. } => (),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,6 +423,14 @@ pub struct ProcessOrder {
     pub post_only: bool,
     pub is_market: bool,
     pub tif: TimeInForce,
+    pub display_amount: Option<Amount>,
+    pub self_trade_action: Option<SelfTradeAction>,
+    #[serde(default)]
+    pub market_to_limit: bool,
+    #[serde(default)]
+    pub min_qty: Option<Amount>,
+    #[serde(default)]
+    pub all_or_none: bool,
 }
 
 impl From<IncomingOrder> for ProcessOrder {
@@ -107,31 +441,101 @@ impl From<IncomingOrder> for ProcessOrder {
             side: order.side,
             amount: order.amount,
             price: match order.order_type {
-                OrderType::Market { .. } => Price::from(0),
-                OrderType::Limit { price, .. } => price,
+                OrderType::Market { .. } | OrderType::StopMarket { .. } => Price::ZERO,
+                OrderType::Limit { price, .. } | OrderType::StopLimit { price, .. } => price,
             },
             is_market: match order.order_type {
-                OrderType::Market { .. } => true,
-                OrderType::Limit { .. } => false,
+                OrderType::Market { .. } | OrderType::StopMarket { .. } => true,
+                OrderType::Limit { .. } | OrderType::StopLimit { .. } => false,
             },
             post_only: match order.order_type {
-                OrderType::Market { .. } => false,
-                OrderType::Limit { post_only, .. } => post_only,
+                OrderType::Market { .. } | OrderType::StopMarket { .. } => false,
+                OrderType::Limit { post_only, .. } | OrderType::StopLimit { post_only, .. } => {
+                    post_only
+                }
             },
             tif: match order.order_type {
-                OrderType::Market { .. } => TimeInForce::GTC,
-                OrderType::Limit { tif, .. } => tif,
+                OrderType::StopMarket { .. } => TimeInForce::IOC,
+                OrderType::Market { tif, .. }
+                | OrderType::Limit { tif, .. }
+                | OrderType::StopLimit { tif, .. } => tif,
+            },
+            display_amount: match order.order_type {
+                OrderType::Limit { display_amount, .. } => display_amount,
+                _ => None,
+            },
+            self_trade_action: order.self_trade_action,
+            market_to_limit: match order.order_type {
+                OrderType::Market {
+                    market_to_limit, ..
+                } => market_to_limit,
+                _ => false,
+            },
+            min_qty: match order.order_type {
+                OrderType::Limit { min_qty, .. } => min_qty,
+                _ => None,
+            },
+            all_or_none: match order.order_type {
+                OrderType::Limit { all_or_none, .. } => all_or_none,
+                _ => false,
             },
         }
     }
 }
 
+impl Prices for ProcessOrder {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        f(&mut self.price)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BookOrder {
     pub order_id: OrderId,
     pub user_id: Uuid,
     pub price: Price,
     pub amount: Amount,
+    #[serde(default)]
+    pub hidden_amount: Amount,
+    #[serde(default)]
+    pub display_amount: Option<Amount>,
+    #[serde(default)]
+    pub all_or_none: bool,
+    #[serde(default)]
+    pub post_only: bool,
+    #[serde(default)]
+    pub self_trade_action: Option<SelfTradeAction>,
+}
+
+impl BookOrder {
+    pub fn total_amount(&self) -> Amount {
+        self.amount + self.hidden_amount
+    }
+}
+
+impl From<&ProcessOrder> for BookOrder {
+    fn from(order: &ProcessOrder) -> Self {
+        let amount = order.display_amount.map_or(order.amount, |display_amount| {
+            display_amount.min(order.amount)
+        });
+        BookOrder {
+            order_id: order.order_id,
+            user_id: order.user_id,
+            price: order.price,
+            amount,
+            hidden_amount: order.amount - amount,
+            display_amount: order.display_amount,
+            all_or_none: order.all_or_none,
+            post_only: order.post_only,
+            self_trade_action: order.self_trade_action,
+        }
+    }
+}
+
+impl Prices for BookOrder {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        f(&mut self.price)
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -145,8 +549,20 @@ pub struct Trade {
     pub taker_order_id: OrderId,
 }
 
+impl Prices for Trade {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        f(&mut self.price)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EngineEvent {
+    OrderAccepted {
+        order_id: OrderId,
+        user_id: Uuid,
+        side: OrderSide,
+        amount: Amount,
+    },
     OrderPlaced {
         order: BookOrder,
         side: OrderSide,
@@ -161,4 +577,295 @@ pub enum EngineEvent {
         order_id: OrderId,
         reason: RejectReason,
     },
+    StopOrderPlaced {
+        order_id: OrderId,
+        side: OrderSide,
+        stop_price: Price,
+    },
+    StopTriggered {
+        order_id: OrderId,
+        stop_price: Price,
+        trade_price: Price,
+    },
+    OrderAmended {
+        order_id: OrderId,
+        side: OrderSide,
+        old_price: Price,
+        new_price: Price,
+        old_amount: Amount,
+        new_amount: Amount,
+    },
+    TradingStatusChanged {
+        status: TradingStatus,
+        reason: StatusReason,
+    },
+    IndicativeUncross {
+        indicative: Option<AuctionPrice>,
+    },
+    ExecutionReport(ExecutionReport),
+}
+
+impl Prices for EngineEvent {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        match self {
+            EngineEvent::OrderPlaced { order, .. } => order.for_each_price(f),
+            EngineEvent::TradeExecuted(trade) => trade.for_each_price(f),
+            EngineEvent::StopOrderPlaced { stop_price, .. } => f(stop_price),
+            EngineEvent::StopTriggered {
+                stop_price,
+                trade_price,
+                ..
+            } => {
+                f(stop_price);
+                f(trade_price);
+            }
+            EngineEvent::OrderAmended {
+                old_price,
+                new_price,
+                ..
+            } => {
+                f(old_price);
+                f(new_price);
+            }
+            EngineEvent::IndicativeUncross { indicative } => indicative.for_each_price(f),
+            // The average price is already in wire units; see `OrderProgress`.
+            EngineEvent::ExecutionReport(_)
+            | EngineEvent::OrderAccepted { .. }
+            | EngineEvent::OrderCancelled { .. }
+            | EngineEvent::OrderRejected { .. }
+            | EngineEvent::TradingStatusChanged { .. } => (),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected,
+}
+
+// State of one order after the event it follows. `remaining_amount` is what
+// is still open, so it is zero once the order is done for any reason.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionReport {
+    pub order_id: OrderId,
+    pub status: OrderStatus,
+    pub filled_amount: Amount,
+    pub remaining_amount: Amount,
+    pub average_price: Option<Price>,
+}
+
+// Fills of a live order, from acceptance until it is filled or cancelled.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrderProgress {
+    pub amount: Amount,
+    pub filled_amount: Amount,
+    // Sum of wire price units times amount over every fill; an average price
+    // usually falls between two ticks.
+    pub notional: i128,
+}
+
+impl OrderProgress {
+    pub fn new(amount: Amount) -> Self {
+        OrderProgress {
+            amount,
+            ..Default::default()
+        }
+    }
+
+    pub fn fill(&mut self, price: Price, amount: Amount) {
+        self.filled_amount += amount;
+        self.notional += price.units() as i128 * amount as i128;
+    }
+
+    pub fn status(&self) -> OrderStatus {
+        if self.filled_amount >= self.amount {
+            OrderStatus::Filled
+        } else if self.filled_amount > 0 {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::New
+        }
+    }
+
+    pub fn report(&self, order_id: OrderId, status: OrderStatus) -> ExecutionReport {
+        let open = matches!(status, OrderStatus::New | OrderStatus::PartiallyFilled);
+        ExecutionReport {
+            order_id,
+            status,
+            filled_amount: self.filled_amount,
+            remaining_amount: if open {
+                self.amount - self.filled_amount
+            } else {
+                0
+            },
+            average_price: (self.filled_amount > 0)
+                .then(|| Price::from_units((self.notional / self.filled_amount as i128) as i64)),
+        }
+    }
+}
+
+// Equilibrium of a call auction: the price and volume it would uncross at,
+// with the total buy and sell interest at that price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuctionPrice {
+    pub price: Price,
+    pub volume: Amount,
+    pub buy_volume: Amount,
+    pub sell_volume: Amount,
+}
+
+impl Prices for AuctionPrice {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        f(&mut self.price)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub symbol: Symbol,
+    pub sequence: u64,
+    pub ingress_time: Timestamp,
+    pub match_time: Timestamp,
+    pub event: EngineEvent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceLevel {
+    pub price: Price,
+    pub amount: Amount,
+}
+
+impl Prices for PriceLevel {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        f(&mut self.price)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MarketDataMessage {
+    DepthUpdate {
+        symbol: Symbol,
+        side: OrderSide,
+        price: Price,
+        amount: Amount,
+    },
+    DepthSnapshot {
+        symbol: Symbol,
+        bids: Vec<PriceLevel>,
+        asks: Vec<PriceLevel>,
+    },
+}
+
+impl MarketDataMessage {
+    pub fn symbol(&self) -> &Symbol {
+        match self {
+            MarketDataMessage::DepthUpdate { symbol, .. } => symbol,
+            MarketDataMessage::DepthSnapshot { symbol, .. } => symbol,
+        }
+    }
+}
+
+impl Prices for MarketDataMessage {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        match self {
+            MarketDataMessage::DepthUpdate { price, .. } => f(price),
+            MarketDataMessage::DepthSnapshot { bids, asks, .. } => {
+                bids.for_each_price(f);
+                asks.for_each_price(f);
+            }
+        }
+    }
+}
+
+// Order-by-order (L3) view of a book. Sizes are the displayed tranche only, so
+// hidden iceberg reserves never appear on the feed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BookChange {
+    Add {
+        order_id: OrderId,
+        price: Price,
+        amount: Amount,
+    },
+    Modify {
+        order_id: OrderId,
+        price: Price,
+        amount: Amount,
+    },
+    Delete {
+        order_id: OrderId,
+        price: Price,
+    },
+    Execute {
+        order_id: OrderId,
+        price: Price,
+        amount: Amount,
+    },
+}
+
+impl Prices for BookChange {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        match self {
+            BookChange::Add { price, .. }
+            | BookChange::Modify { price, .. }
+            | BookChange::Delete { price, .. }
+            | BookChange::Execute { price, .. } => f(price),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestingOrder {
+    pub order_id: OrderId,
+    pub price: Price,
+    pub amount: Amount,
+}
+
+impl Prices for RestingOrder {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        f(&mut self.price)
+    }
+}
+
+// `sequence` is per symbol and gap-free. A snapshot carries the sequence of the
+// last update it already reflects, so a consumer that detects a gap discards
+// its book and resumes from the next snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OrderBookMessage {
+    Update {
+        symbol: Symbol,
+        sequence: u64,
+        side: OrderSide,
+        change: BookChange,
+    },
+    Snapshot {
+        symbol: Symbol,
+        sequence: u64,
+        bids: Vec<RestingOrder>,
+        asks: Vec<RestingOrder>,
+    },
+}
+
+impl OrderBookMessage {
+    pub fn symbol(&self) -> &Symbol {
+        match self {
+            OrderBookMessage::Update { symbol, .. } => symbol,
+            OrderBookMessage::Snapshot { symbol, .. } => symbol,
+        }
+    }
+}
+
+impl Prices for OrderBookMessage {
+    fn for_each_price(&mut self, f: &mut impl FnMut(&mut Price)) {
+        match self {
+            OrderBookMessage::Update { change, .. } => change.for_each_price(f),
+            OrderBookMessage::Snapshot { bids, asks, .. } => {
+                bids.for_each_price(f);
+                asks.for_each_price(f);
+            }
+        }
+    }
 }
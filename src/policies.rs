@@ -2,9 +2,12 @@ use anyhow::Result;
 use uuid::Uuid;
 
 use crate::{
+    config::{Fraction, TradingRules},
+    matcher::Matcher,
     model::{
-        CancelReason, EngineEvent, OrderSide, Price, ProcessOrder, RejectReason, SelfTradeAction,
-        TimeInForce,
+        AMOUNT_SCALE, Amount, CancelReason, EngineEvent, OrderId, OrderSide, PRICE_SCALE, Price,
+        ProcessOrder, RejectReason, SelfTradeAction, TickSize, TimeInForce, Timestamp,
+        TradingStatus,
     },
     storage::{BookSide, PriceKey},
 };
@@ -27,24 +30,60 @@ impl PolicyChecker {
         }
     }
 
+    // Fill-or-kill and minimum quantities are decided by a dry run of the
+    // match itself, so makers the order could never trade with don't count.
     pub fn check_liquidity<K: PriceKey>(
         order: &ProcessOrder,
         book: &BookSide<K>,
+        self_trade_action: SelfTradeAction,
+        band_limit: Option<Price>,
     ) -> Result<(), EngineEvent> {
-        if match order.tif {
-            TimeInForce::FOK => false,
-            TimeInForce::GTC => true,
-            TimeInForce::IOC => true,
-        } {
-            return Ok(());
-        }
-
-        if order.amount > book.get_liquidity(order.price) {
+        let (required, reason) = match (order.tif, order.min_qty) {
+            (TimeInForce::FOK, _) => (order.amount, CancelReason::FokLiquidityShortage),
+            (_, Some(min_qty)) => (min_qty, CancelReason::MinQuantityShortage),
+            _ => return Ok(()),
+        };
+        if Matcher::fillable_amount(order, book, self_trade_action, band_limit) < required {
             return Err(EngineEvent::OrderCancelled {
                 order_id: order.order_id,
                 remaining_amount: order.amount,
-                reason: CancelReason::FokLiquidityShortage,
+                reason,
+            });
+        }
+        Ok(())
+    }
+
+    // While halted nothing may trade, so orders that would take liquidity are
+    // rejected; orders that only add to the book are still accepted. Pre-open
+    // and auction phases only collect resting limit orders.
+    pub fn check_trading_status<K: PriceKey>(
+        order: &ProcessOrder,
+        status: TradingStatus,
+        book: &BookSide<K>,
+    ) -> Result<(), EngineEvent> {
+        let reject = |reason| {
+            Err(EngineEvent::OrderRejected {
+                order_id: order.order_id,
+                reason,
+            })
+        };
+        match status {
+            TradingStatus::Continuous => return Ok(()),
+            TradingStatus::Closed => return reject(RejectReason::MarketClosed),
+            TradingStatus::PreOpen | TradingStatus::Auction
+                if order.is_market || matches!(order.tif, TimeInForce::IOC | TimeInForce::FOK) =>
+            {
+                return reject(RejectReason::NotAllowedInSession);
+            }
+            TradingStatus::PreOpen | TradingStatus::Auction => return Ok(()),
+            TradingStatus::Halted => (),
+        }
+        let aggressing = order.is_market
+            || book.best_price().is_some_and(|best_price| {
+                Self::check_price_match(order.side, best_price, order.price, false)
             });
+        if aggressing {
+            return reject(RejectReason::TradingHalted);
         }
         Ok(())
     }
@@ -63,6 +102,8 @@ impl PolicyChecker {
             TimeInForce::FOK => true,
             TimeInForce::GTC => false,
             TimeInForce::IOC => true,
+            TimeInForce::GTD(_) => false,
+            TimeInForce::DAY => false,
         } {
             return Err(EngineEvent::OrderRejected {
                 order_id: order.order_id,
@@ -72,9 +113,144 @@ impl PolicyChecker {
         Ok(())
     }
 
-    pub fn check_self_trade(agressor_user_id: Uuid, order_user_id: Uuid) -> SelfTradeAction {
+    pub fn check_display_amount(order: &ProcessOrder) -> Result<(), EngineEvent> {
+        if order.display_amount == Some(0) {
+            return Err(EngineEvent::OrderRejected {
+                order_id: order.order_id,
+                reason: RejectReason::InvalidAmount,
+            });
+        }
+        Ok(())
+    }
+
+    // Limit prices, stop prices and amounts have to sit on the instrument's
+    // grid and within its size limits. Market orders have no price to check,
+    // so min notional only applies to limit orders.
+    pub fn check_trading_rules(
+        order: &ProcessOrder,
+        stop_price: Option<Price>,
+        rules: &TradingRules,
+        tick_size: TickSize,
+    ) -> Result<(), EngineEvent> {
+        let reject = |reason| EngineEvent::OrderRejected {
+            order_id: order.order_id,
+            reason,
+        };
+        if let Some(stop_price) = stop_price
+            && !Self::is_valid_price(stop_price)
+        {
+            return Err(reject(RejectReason::InvalidPrice));
+        }
+        if !order.is_market && !Self::is_valid_price(order.price) {
+            return Err(reject(RejectReason::InvalidPrice));
+        }
+        // A market order can only outlive its arrival as a converted limit.
+        if order.is_market
+            && !order.market_to_limit
+            && !matches!(order.tif, TimeInForce::IOC | TimeInForce::FOK)
+        {
+            return Err(reject(RejectReason::InvalidTimeInForce));
+        }
+        if let Some(min_qty) = order.min_qty
+            && (!matches!(order.tif, TimeInForce::IOC) || min_qty == 0 || min_qty > order.amount)
+        {
+            return Err(reject(RejectReason::InvalidMinQuantity));
+        }
+        // An all-or-none order has to rest to wait for a big enough
+        // counterparty, and has to show its whole size to get one.
+        if order.all_or_none
+            && (order.is_market
+                || order.display_amount.is_some()
+                || matches!(order.tif, TimeInForce::IOC | TimeInForce::FOK))
+        {
+            return Err(reject(RejectReason::InvalidAllOrNone));
+        }
+        Self::check_amount(order.order_id, order.amount, rules)?;
+        if let Some(display_amount) = order.display_amount
+            && rules
+                .lot_size
+                .is_some_and(|lot_size| !display_amount.is_multiple_of(lot_size))
+        {
+            return Err(reject(RejectReason::InvalidAmount));
+        }
+        if !order.is_market
+            && let Some(min_notional) = rules.min_notional
+            && (tick_size.to_wire(order.price).units() as i128 * order.amount as i128)
+                < (min_notional.units() as i128 * AMOUNT_SCALE as i128)
+        {
+            return Err(reject(RejectReason::BelowMinNotional));
+        }
+        Ok(())
+    }
+
+    pub fn check_amount(
+        order_id: OrderId,
+        amount: Amount,
+        rules: &TradingRules,
+    ) -> Result<(), EngineEvent> {
+        let invalid = amount == 0
+            || rules
+                .lot_size
+                .is_some_and(|lot_size| !amount.is_multiple_of(lot_size))
+            || rules
+                .min_amount
+                .is_some_and(|min_amount| amount < min_amount)
+            || rules
+                .max_amount
+                .is_some_and(|max_amount| amount > max_amount);
+        if invalid {
+            return Err(EngineEvent::OrderRejected {
+                order_id,
+                reason: RejectReason::InvalidAmount,
+            });
+        }
+        Ok(())
+    }
+
+    // Prices off the tick never get this far; they can't be put in ticks.
+    pub fn is_valid_price(price: Price) -> bool {
+        price > Price::ZERO
+    }
+
+    // Worst price an order may trade at: the edge of the band on its side of
+    // the reference price, rounded towards the reference.
+    pub fn band_limit(
+        side: OrderSide,
+        reference_price: Option<Price>,
+        band: Option<Fraction>,
+    ) -> Option<Price> {
+        let reference_price = reference_price?.units() as i128;
+        let (band, scale) = (band?.units() as i128, PRICE_SCALE as i128);
+        let units = match side {
+            OrderSide::Buy => reference_price * (scale + band) / scale,
+            OrderSide::Sell => (reference_price * (scale - band) + scale - 1) / scale,
+        };
+        i64::try_from(units).ok().map(Price::from_units)
+    }
+
+    pub fn is_outside_band(side: OrderSide, price: Price, band_limit: Option<Price>) -> bool {
+        band_limit.is_some_and(|limit| !Self::check_price_match(side, price, limit, false))
+    }
+
+    pub fn check_expiry(order: &ProcessOrder, now: Timestamp) -> Result<(), EngineEvent> {
+        if let TimeInForce::GTD(expiry) = order.tif
+            && expiry <= now
+        {
+            return Err(EngineEvent::OrderRejected {
+                order_id: order.order_id,
+                reason: RejectReason::InvalidExpiry,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_self_trade(
+        self_trade_action: SelfTradeAction,
+        agressor_user_id: Uuid,
+        order_user_id: Uuid,
+    ) -> SelfTradeAction {
         if agressor_user_id == order_user_id {
-            return SelfTradeAction::Skip;
+            return self_trade_action;
         }
         SelfTradeAction::Allow
     }
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use rustc_hash::FxHashMap;
+use smallvec::{SmallVec, smallvec};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    config::InstrumentConfig,
+    engine::{EngineSnapshot, MatchEngine},
+    model::{
+        Command, EngineEvent, EventEnvelope, MarketDataMessage, OrderBookMessage, RejectReason,
+        Symbol, Timestamp,
+    },
+};
+
+pub struct EngineRegistry {
+    engines: FxHashMap<Symbol, MatchEngine>,
+    clock: Box<dyn Clock>,
+}
+
+impl EngineRegistry {
+    pub fn new(instruments: &[InstrumentConfig]) -> Self {
+        Self::with_clock(instruments, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(instruments: &[InstrumentConfig], clock: Box<dyn Clock>) -> Self {
+        EngineRegistry {
+            engines: instruments
+                .iter()
+                .map(|instrument| (instrument.symbol.clone(), MatchEngine::new(instrument)))
+                .collect(),
+            clock,
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<EngineSnapshot> {
+        self.engines.values().map(MatchEngine::snapshot).collect()
+    }
+
+    pub fn restore(&mut self, snapshots: Vec<EngineSnapshot>) -> Result<()> {
+        for snapshot in snapshots {
+            match self.engines.get_mut(&snapshot.symbol) {
+                Some(engine) => engine.restore(snapshot)?,
+                None => eprintln!("Skipping snapshot of unknown symbol {}", snapshot.symbol),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn depth_updates(&mut self, symbol: &Symbol) -> Vec<MarketDataMessage> {
+        match self.engines.get_mut(symbol) {
+            Some(engine) => engine.depth_updates(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn depth_snapshots(&self, levels: usize) -> Vec<MarketDataMessage> {
+        self.engines
+            .values()
+            .map(|engine| engine.depth_snapshot(levels))
+            .collect()
+    }
+
+    pub fn order_book_updates(&mut self, symbol: &Symbol) -> Vec<OrderBookMessage> {
+        match self.engines.get_mut(symbol) {
+            Some(engine) => engine.order_book_updates(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn order_book_snapshots(&self) -> Vec<OrderBookMessage> {
+        self.engines
+            .values()
+            .map(MatchEngine::order_book_snapshot)
+            .collect()
+    }
+
+    // Ticks for every instrument with a scheduled transition or an order
+    // expiry due. They go out through the command topic, so they are
+    // journaled and replayed like any other command.
+    pub fn due_ticks(&self) -> Vec<Command> {
+        let now = self.clock.now();
+        self.engines
+            .iter()
+            .filter(|(_, engine)| engine.has_due_transition(now) || engine.has_due_expiry(now))
+            .map(|(symbol, _)| Command::Tick {
+                symbol: symbol.clone(),
+            })
+            .collect()
+    }
+
+    pub fn process(
+        &mut self,
+        command: Command,
+        ingress_time: Timestamp,
+    ) -> SmallVec<[EventEnvelope; 16]> {
+        let match_time = self.clock.now();
+        if let Some(engine) = self.engines.get_mut(command.symbol()) {
+            return engine.process(command, ingress_time, match_time);
+        }
+        // Unknown symbols have no book and so no sequence of their own.
+        match command.order_id() {
+            Some(order_id) => smallvec![EventEnvelope {
+                symbol: command.symbol().clone(),
+                sequence: 0,
+                ingress_time,
+                match_time,
+                event: EngineEvent::OrderRejected {
+                    order_id,
+                    reason: RejectReason::SymbolNotFound,
+                },
+            }],
+            None => SmallVec::new(),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::Result;
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::EngineSnapshot;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub engines: Vec<EngineSnapshot>,
+    pub offsets: FxHashMap<i32, i64>,
+    pub journal_sequence: Option<u64>,
+}
+
+pub struct SnapshotStore {
+    connection: MultiplexedConnection,
+    key: String,
+}
+
+impl SnapshotStore {
+    pub async fn new(redis_url: &str, key: &str) -> Result<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+
+        Ok(SnapshotStore {
+            connection,
+            key: key.to_string(),
+        })
+    }
+
+    pub async fn save(&mut self, snapshot: &Snapshot) -> Result<()> {
+        let payload = serde_json::to_vec(snapshot)?;
+        let _: () = self.connection.set(&self.key, payload).await?;
+        Ok(())
+    }
+
+    pub async fn load(&mut self) -> Result<Option<Snapshot>> {
+        let payload: Option<Vec<u8>> = self.connection.get(&self.key).await?;
+        match payload {
+            Some(payload) => Ok(Some(serde_json::from_slice(&payload)?)),
+            None => Ok(None),
+        }
+    }
+}
@@ -1,9 +1,14 @@
 #![allow(dead_code)]
-use std::{cmp::Reverse, collections::BTreeMap};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    ops::Bound,
+};
 
 use rustc_hash::FxHashMap;
+use slab::Slab;
 
-use crate::model::{Amount, BookOrder, OrderId, Price};
+use crate::model::{Amount, BookChange, BookOrder, OrderId, Price, ProcessOrder, Timestamp};
 
 pub trait PriceKey: Ord + Copy + Clone {
     fn from_price(price: Price) -> Self;
@@ -28,127 +33,514 @@ impl PriceKey for Reverse<Price> {
     }
 }
 
+pub type Sequence = u64;
+
+// Orders live in a slab and each price level threads its orders into a
+// doubly linked FIFO list, so inserts, cancels and fills never shift other
+// orders around.
 pub struct BookSide<K: PriceKey> {
-    orders: BTreeMap<(K, OrderId), BookOrder>,
-    liquidity_index: LiquidityIndex<K>,
-    index: OrderIndex,
+    orders: Slab<Node>,
+    levels: BTreeMap<K, Level>,
+    changed_levels: BTreeSet<K>,
+    changes: Vec<BookChange>,
+    index: OrderIndex<usize>,
+}
+
+struct Node {
+    order: BookOrder,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+// A level only exists while it holds orders, so it always has a head and tail.
+// `all_or_none` is the part of `total` resting under that condition. Those
+// orders can't be hit by just anyone, so they aren't part of `displayed`,
+// the size the depth feed and the best price go by.
+struct Level {
+    head: usize,
+    tail: usize,
+    total: Amount,
+    displayed: Amount,
+    all_or_none: Amount,
+}
+
+impl Level {
+    fn all_or_none(order: &BookOrder, amount: Amount) -> Amount {
+        if order.all_or_none { amount } else { 0 }
+    }
+
+    fn displayed(order: &BookOrder, amount: Amount) -> Amount {
+        if order.all_or_none { 0 } else { amount }
+    }
 }
 
 impl<K: PriceKey> BookSide<K> {
     pub fn new() -> Self {
         BookSide {
-            orders: BTreeMap::new(),
-            liquidity_index: LiquidityIndex::new(),
+            orders: Slab::new(),
+            levels: BTreeMap::new(),
+            changed_levels: BTreeSet::new(),
+            changes: Vec::new(),
             index: OrderIndex::new(),
         }
     }
 
+    // Every insert goes to the back of its price level, so re-inserting an
+    // existing order is how it loses time priority.
     pub fn insert(&mut self, order: BookOrder) {
-        let key: (K, OrderId) = (K::from_price(order.price), order.order_id);
-        self.index.insert(order.order_id, order.price);
-        self.liquidity_index
-            .add_liquidity(K::from_price(order.price), order.amount);
-        self.orders.insert(key, order);
+        debug_assert!(
+            self.index.get(order.order_id).is_none(),
+            "order {} is already in the book",
+            order.order_id
+        );
+        self.changes.push(BookChange::Add {
+            order_id: order.order_id,
+            price: order.price,
+            amount: order.amount,
+        });
+        let order_id = order.order_id;
+        let key = self.orders.insert(Node {
+            order,
+            prev: None,
+            next: None,
+        });
+        self.index.insert(order_id, key);
+        self.attach(key);
     }
 
     pub fn remove(&mut self, order_id: OrderId) -> Option<BookOrder> {
-        let key = (K::from_price(*self.index.get(order_id)), order_id);
-        let order = self.orders.remove(&key)?;
-        self.index.remove(order_id);
-        self.liquidity_index
-            .remove_liquidity(K::from_price(order.price), order.amount);
+        let key = self.index.remove(order_id)?;
+        self.detach(key);
+        let order = self.orders.remove(key).order;
+        self.changes.push(BookChange::Delete {
+            order_id,
+            price: order.price,
+        });
         Some(order)
     }
 
+    pub fn get(&self, order_id: OrderId) -> Option<&BookOrder> {
+        Some(&self.orders[self.index.get(order_id)?].order)
+    }
+
+    // Shrinks a resting order in place, keeping its time priority. The hidden
+    // reserve is reduced before the displayed tranche.
+    pub fn reduce(&mut self, order_id: OrderId, total_amount: Amount) -> Option<&BookOrder> {
+        let key = self.index.get(order_id)?;
+        let order = &mut self.orders[key].order;
+        if total_amount > order.total_amount() {
+            return None;
+        }
+        let amount = order.amount.min(total_amount);
+        let removed_total = order.total_amount() - total_amount;
+        let removed_displayed = order.amount - amount;
+        order.amount = amount;
+        order.hidden_amount = total_amount - amount;
+
+        let price_key = K::from_price(order.price);
+        let removed_from_depth = Level::displayed(order, removed_displayed);
+        if let Some(level) = self.levels.get_mut(&price_key) {
+            level.total -= removed_total;
+            level.displayed -= removed_from_depth;
+            level.all_or_none -= Level::all_or_none(order, removed_total);
+        }
+        if removed_from_depth > 0 {
+            self.changed_levels.insert(price_key);
+        }
+        if removed_displayed > 0 {
+            self.changes.push(BookChange::Modify {
+                order_id,
+                price: price_key.as_price(),
+                amount,
+            });
+        }
+        Some(&self.orders[key].order)
+    }
+
+    // Executes against the displayed tranche of a resting order. Once the
+    // tranche is exhausted it is refreshed from the hidden reserve and the
+    // order moves to the back of its price level.
+    pub fn fill(&mut self, order_id: OrderId, amount: Amount) {
+        let Some(key) = self.index.get(order_id) else {
+            return;
+        };
+        let order = &mut self.orders[key].order;
+        let amount = amount.min(order.amount);
+        order.amount -= amount;
+        let price = order.price;
+        let exhausted = order.amount == 0;
+        let hidden_amount = order.hidden_amount;
+        let all_or_none = Level::all_or_none(order, amount);
+        let displayed = Level::displayed(order, amount);
+
+        self.changes.push(BookChange::Execute {
+            order_id,
+            price,
+            amount,
+        });
+        let price_key = K::from_price(price);
+        if let Some(level) = self.levels.get_mut(&price_key) {
+            level.total -= amount;
+            level.displayed -= displayed;
+            level.all_or_none -= all_or_none;
+        }
+        if displayed > 0 {
+            self.changed_levels.insert(price_key);
+        }
+        if !exhausted {
+            return;
+        }
+        if hidden_amount == 0 {
+            self.remove(order_id);
+            return;
+        }
+
+        self.detach(key);
+        self.changes.push(BookChange::Delete { order_id, price });
+        let order = &mut self.orders[key].order;
+        let display_amount = order.display_amount.unwrap_or(hidden_amount);
+        order.amount = display_amount.min(hidden_amount);
+        order.hidden_amount -= order.amount;
+        self.changes.push(BookChange::Add {
+            order_id,
+            price,
+            amount: order.amount,
+        });
+        self.attach(key);
+    }
+
+    // Links the order at `key` to the back of its level and counts its size.
+    fn attach(&mut self, key: usize) {
+        let order = &self.orders[key].order;
+        let price_key = K::from_price(order.price);
+        let (total, displayed) = (order.total_amount(), Level::displayed(order, order.amount));
+        let all_or_none = Level::all_or_none(order, total);
+        match self.levels.get_mut(&price_key) {
+            Some(level) => {
+                self.orders[level.tail].next = Some(key);
+                self.orders[key].prev = Some(level.tail);
+                level.tail = key;
+                level.total += total;
+                level.displayed += displayed;
+                level.all_or_none += all_or_none;
+            }
+            None => {
+                self.levels.insert(
+                    price_key,
+                    Level {
+                        head: key,
+                        tail: key,
+                        total,
+                        displayed,
+                        all_or_none,
+                    },
+                );
+            }
+        }
+        if displayed > 0 {
+            self.changed_levels.insert(price_key);
+        }
+    }
+
+    // Unlinks the order at `key` from its level, leaving it in the slab.
+    fn detach(&mut self, key: usize) {
+        let node = &mut self.orders[key];
+        let (prev, next) = (node.prev.take(), node.next.take());
+        let price_key = K::from_price(node.order.price);
+        let total = node.order.total_amount();
+        let displayed = Level::displayed(&node.order, node.order.amount);
+        let all_or_none = Level::all_or_none(&node.order, total);
+        if let Some(prev) = prev {
+            self.orders[prev].next = next;
+        }
+        if let Some(next) = next {
+            self.orders[next].prev = prev;
+        }
+        let Some(level) = self.levels.get_mut(&price_key) else {
+            return;
+        };
+        match (prev, next) {
+            (None, None) => {
+                self.levels.remove(&price_key);
+            }
+            _ => {
+                if prev.is_none() {
+                    level.head = next.unwrap_or(level.head);
+                }
+                if next.is_none() {
+                    level.tail = prev.unwrap_or(level.tail);
+                }
+                level.total -= total;
+                level.displayed -= displayed;
+                level.all_or_none -= all_or_none;
+            }
+        }
+        if displayed > 0 {
+            self.changed_levels.insert(price_key);
+        }
+    }
+
+    // Best price anyone can trade at, so levels holding nothing but
+    // all-or-none orders don't count.
     pub fn best_price(&self) -> Option<Price> {
-        self.orders
-            .keys()
-            .next()
+        self.levels
+            .iter()
+            .find(|(_, level)| level.total > level.all_or_none)
             .map(|(price_key, _)| price_key.as_price())
     }
 
+    pub fn best_order(&self) -> Option<&BookOrder> {
+        let (_, level) = self.levels.iter().next()?;
+        Some(&self.orders[level.head].order)
+    }
+
+    // The best order passing `filter`.
     pub fn peek_best<F>(&self, filter: F) -> Option<&BookOrder>
     where
         F: Fn(&BookOrder) -> bool,
     {
-        self.orders.values().find(|order| filter(order))
+        self.peek_from(self.best_order()?.order_id, filter)
+    }
+
+    // The order queued directly behind `order_id`, which is the head of the
+    // next level once its own level runs out.
+    pub fn next_order(&self, order_id: OrderId) -> Option<&BookOrder> {
+        let key = self.next_key(self.index.get(order_id)?)?;
+        Some(&self.orders[key].order)
     }
 
-    pub fn pop_best<F>(&mut self, filter: F) -> Option<BookOrder>
+    // The first order passing `filter`, walking in priority order from
+    // `order_id` itself.
+    pub fn peek_from<F>(&self, order_id: OrderId, filter: F) -> Option<&BookOrder>
     where
         F: Fn(&BookOrder) -> bool,
     {
-        let key = self
-            .orders
-            .iter()
-            .find(|(_, order)| filter(order))
-            .map(|(key, _)| *key)?;
-        let order = self.orders.remove(&key)?;
+        let mut key = self.index.get(order_id)?;
+        loop {
+            let order = &self.orders[key].order;
+            if filter(order) {
+                return Some(order);
+            }
+            key = self.next_key(key)?;
+        }
+    }
 
-        self.liquidity_index
-            .remove_liquidity(K::from_price(order.price), order.amount);
-        self.index.remove(order.order_id);
-        Some(order)
+    fn next_key(&self, key: usize) -> Option<usize> {
+        let node = &self.orders[key];
+        if let Some(next) = node.next {
+            return Some(next);
+        }
+        let price_key = K::from_price(node.order.price);
+        let (_, level) = self
+            .levels
+            .range((Bound::Excluded(price_key), Bound::Unbounded))
+            .next()?;
+        Some(level.head)
     }
 
+    // Size available at `price` or better to a call auction, which
+    // all-or-none orders sit out.
     pub fn get_liquidity(&self, price: Price) -> Amount {
-        self.liquidity_index.get_liquidity(K::from_price(price))
+        self.levels
+            .range(..=K::from_price(price))
+            .map(|(_, level)| level.total - level.all_or_none)
+            .sum()
+    }
+
+    // Current displayed size of every level touched since the last call; a
+    // size of zero means the level is gone.
+    pub fn drain_depth_changes(&mut self) -> Vec<(Price, Amount)> {
+        std::mem::take(&mut self.changed_levels)
+            .into_iter()
+            .map(|price_key| {
+                (
+                    price_key.as_price(),
+                    self.levels
+                        .get(&price_key)
+                        .map_or(0, |level| level.displayed),
+                )
+            })
+            .collect()
+    }
+
+    // Order-level mutations since the last call, in the order they happened.
+    // A fully executed order shows up as an execute followed by a delete, and
+    // an iceberg refresh as a delete and an add at the back of the level.
+    pub fn drain_changes(&mut self) -> Vec<BookChange> {
+        std::mem::take(&mut self.changes)
+    }
+
+    // Total size per price level, hidden reserves included, best first.
+    pub fn levels(&self) -> impl Iterator<Item = (Price, Amount)> {
+        self.levels
+            .iter()
+            .map(|(price_key, level)| (price_key.as_price(), level.total))
+    }
+
+    // Displayed size of the best `levels` price levels, hidden reserves excluded.
+    pub fn depth(&self, levels: usize) -> Vec<(Price, Amount)> {
+        self.levels
+            .iter()
+            .filter(|(_, level)| level.displayed > 0)
+            .take(levels)
+            .map(|(price_key, level)| (price_key.as_price(), level.displayed))
+            .collect()
     }
 
+    // Resting orders in priority order.
     pub fn iter(&self) -> impl Iterator<Item = &BookOrder> {
-        self.orders.values()
+        self.levels.values().flat_map(|level| {
+            std::iter::successors(Some(level.head), |key| self.orders[*key].next)
+                .map(|key| &self.orders[key].order)
+        })
+    }
+}
+
+impl<K: PriceKey> Default for BookSide<K> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-struct LiquidityIndex<K: PriceKey> {
-    index: BTreeMap<K, Amount>,
+// Stop orders waiting for a trade to cross their stop price. Keys are ordered
+// so the first entry is always the next one to trigger.
+pub struct TriggerSide<K: PriceKey> {
+    orders: BTreeMap<(K, Sequence), ProcessOrder>,
+    index: OrderIndex<(Price, Sequence)>,
+    next_sequence: Sequence,
 }
 
-impl<K: PriceKey> LiquidityIndex<K> {
+impl<K: PriceKey> TriggerSide<K> {
     pub fn new() -> Self {
-        LiquidityIndex {
-            index: BTreeMap::new(),
+        TriggerSide {
+            orders: BTreeMap::new(),
+            index: OrderIndex::new(),
+            next_sequence: 0,
         }
     }
 
-    pub fn add_liquidity(&mut self, price: K, amount: Amount) {
-        self.index
-            .entry(price)
-            .and_modify(|a| *a += amount)
-            .or_insert(amount);
+    pub fn insert(&mut self, stop_price: Price, order: ProcessOrder) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.index.insert(order.order_id, (stop_price, sequence));
+        self.orders
+            .insert((K::from_price(stop_price), sequence), order);
     }
 
-    pub fn remove_liquidity(&mut self, price: K, amount: Amount) {
-        if let Some(level) = self.index.get_mut(&price) {
-            *level -= amount;
-            if *level == 0 {
-                self.index.remove(&price);
-            }
+    pub fn remove(&mut self, order_id: OrderId) -> Option<ProcessOrder> {
+        let (stop_price, sequence) = self.index.get(order_id)?;
+        let order = self.orders.remove(&(K::from_price(stop_price), sequence))?;
+        self.index.remove(order_id);
+        Some(order)
+    }
+
+    pub fn get(&self, order_id: OrderId) -> Option<&ProcessOrder> {
+        let (stop_price, sequence) = self.index.get(order_id)?;
+        self.orders.get(&(K::from_price(stop_price), sequence))
+    }
+
+    pub fn pop_triggered(&mut self, trade_price: Price) -> Option<(Price, ProcessOrder)> {
+        let (stop_key, _) = self.orders.keys().next()?;
+        if *stop_key > K::from_price(trade_price) {
+            return None;
+        }
+        let ((stop_key, _), order) = self.orders.pop_first()?;
+        self.index.remove(order.order_id);
+        Some((stop_key.as_price(), order))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Price, &ProcessOrder)> {
+        self.orders
+            .iter()
+            .map(|((stop_key, _), order)| (stop_key.as_price(), order))
+    }
+}
+
+impl<K: PriceKey> Default for TriggerSide<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Trade prices over a sliding time window. Each queue only keeps prices that
+// can still become the window's low or high, so both sit at the front.
+pub struct PriceWindow {
+    lows: VecDeque<(Timestamp, Price)>,
+    highs: VecDeque<(Timestamp, Price)>,
+}
+
+impl PriceWindow {
+    pub fn new() -> Self {
+        PriceWindow {
+            lows: VecDeque::new(),
+            highs: VecDeque::new(),
         }
     }
 
-    pub fn get_liquidity(&self, price: K) -> Amount {
-        self.index.range(..=price).map(|(_, amount)| amount).sum()
+    // Prices must be pushed in time order.
+    pub fn push(&mut self, time: Timestamp, price: Price) {
+        while self.lows.back().is_some_and(|(_, low)| *low >= price) {
+            self.lows.pop_back();
+        }
+        while self.highs.back().is_some_and(|(_, high)| *high <= price) {
+            self.highs.pop_back();
+        }
+        self.lows.push_back((time, price));
+        self.highs.push_back((time, price));
+    }
+
+    // Drops every price recorded before `start`.
+    pub fn expire(&mut self, start: Timestamp) {
+        while self.lows.front().is_some_and(|(time, _)| *time < start) {
+            self.lows.pop_front();
+        }
+        while self.highs.front().is_some_and(|(time, _)| *time < start) {
+            self.highs.pop_front();
+        }
+    }
+
+    pub fn range(&self) -> Option<(Price, Price)> {
+        Some((self.lows.front()?.1, self.highs.front()?.1))
+    }
+
+    pub fn clear(&mut self) {
+        self.lows.clear();
+        self.highs.clear();
+    }
+
+    // The prices that can still set the low or high, oldest first. Pushing
+    // them into an empty window rebuilds this one.
+    pub fn entries(&self) -> Vec<(Timestamp, Price)> {
+        let mut entries: Vec<_> = self.lows.iter().chain(&self.highs).copied().collect();
+        entries.sort();
+        entries.dedup();
+        entries
+    }
+}
+
+impl Default for PriceWindow {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-struct OrderIndex {
-    index: FxHashMap<OrderId, Price>,
+struct OrderIndex<V: Copy> {
+    index: FxHashMap<OrderId, V>,
 }
 
-impl OrderIndex {
+impl<V: Copy> OrderIndex<V> {
     pub fn new() -> Self {
         OrderIndex {
             index: FxHashMap::default(),
         }
     }
-    pub fn insert(&mut self, order_id: OrderId, price: Price) {
-        self.index.insert(order_id, price);
+    pub fn insert(&mut self, order_id: OrderId, entry: V) {
+        self.index.insert(order_id, entry);
     }
-    pub fn remove(&mut self, order_id: OrderId) {
-        self.index.remove(&order_id);
+    pub fn remove(&mut self, order_id: OrderId) -> Option<V> {
+        self.index.remove(&order_id)
     }
-    pub fn get(&self, order_id: OrderId) -> &Price {
-        self.index.get(&order_id).unwrap()
+    pub fn get(&self, order_id: OrderId) -> Option<V> {
+        self.index.get(&order_id).copied()
     }
 }
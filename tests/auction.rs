@@ -0,0 +1,110 @@
+mod support;
+
+use match_engine::{
+    config::TradingRules,
+    engine::MatchEngine,
+    model::{EngineEvent, OrderSide, Price, TradingStatus},
+};
+
+use support::{engine, limit, place, set_status, submit, trades};
+
+// Collects the orders in an auction, then reopens and returns the uncross.
+fn uncross(
+    rules: TradingRules,
+    bids: &[(i64, u64)],
+    asks: &[(i64, u64)],
+) -> (MatchEngine, Vec<EngineEvent>) {
+    let mut engine = engine(rules);
+    submit(&mut engine, set_status(TradingStatus::Auction), 0);
+    let orders = bids
+        .iter()
+        .map(|(price, amount)| (OrderSide::Buy, price, amount))
+        .chain(
+            asks.iter()
+                .map(|(price, amount)| (OrderSide::Sell, price, amount)),
+        );
+    for (order_id, (side, price, amount)) in orders.enumerate() {
+        let user_id = order_id as u128 + 1;
+        place(
+            &mut engine,
+            limit(order_id as u64, user_id, side, *price, *amount),
+        );
+    }
+    let events = submit(&mut engine, set_status(TradingStatus::Continuous), 0);
+    (engine, events)
+}
+
+// Every trade of the uncross prints at `price`; returns the total volume.
+fn uncrossed_at(events: &[EngineEvent], price: i64) -> u64 {
+    let trades = trades(events);
+    assert!(!trades.is_empty());
+    assert!(trades.iter().all(|trade| trade.price == Price::from(price)));
+    trades.iter().map(|trade| trade.amount).sum()
+}
+
+#[test]
+fn uncross_maximizes_volume() {
+    let (_, events) = uncross(
+        TradingRules::default(),
+        &[(101, 10), (100, 10)],
+        &[(99, 5), (100, 10)],
+    );
+    assert_eq!(uncrossed_at(&events, 100), 15);
+}
+
+#[test]
+fn volume_tie_goes_to_smallest_imbalance() {
+    let (_, events) = uncross(
+        TradingRules::default(),
+        &[(102, 10)],
+        &[(100, 10), (101, 5)],
+    );
+    assert_eq!(uncrossed_at(&events, 100), 10);
+}
+
+#[test]
+fn imbalance_tie_goes_to_surplus_side() {
+    let (_, events) = uncross(TradingRules::default(), &[(102, 20)], &[(100, 10)]);
+    assert_eq!(uncrossed_at(&events, 102), 10);
+
+    let (_, events) = uncross(TradingRules::default(), &[(102, 10)], &[(100, 20)]);
+    assert_eq!(uncrossed_at(&events, 100), 10);
+}
+
+#[test]
+fn balanced_tie_goes_to_reference_price() {
+    for (reference, expected) in [(99, 100), (105, 102)] {
+        let rules = TradingRules {
+            reference_price: Some(Price::from(reference)),
+            ..TradingRules::default()
+        };
+        let (_, events) = uncross(rules, &[(102, 10)], &[(100, 10)]);
+        assert_eq!(uncrossed_at(&events, expected), 10);
+    }
+}
+
+#[test]
+fn auction_publishes_indicative_price() {
+    let mut engine = engine(TradingRules::default());
+    submit(&mut engine, set_status(TradingStatus::Auction), 0);
+    place(&mut engine, limit(1, 1, OrderSide::Buy, 101, 10));
+    let events = place(&mut engine, limit(2, 2, OrderSide::Sell, 100, 4));
+    assert!(trades(&events).is_empty());
+    let indicative = events.iter().find_map(|event| match event {
+        EngineEvent::IndicativeUncross { indicative } => *indicative,
+        _ => None,
+    });
+    let indicative = indicative.expect("indicative uncross");
+    assert_eq!(indicative.price, Price::from(101));
+    assert_eq!(indicative.volume, 4);
+}
+
+// The uncross leaves the book uncrossed, with the remainder resting.
+#[test]
+fn uncross_leaves_remainder_resting() {
+    let (mut engine, events) = uncross(TradingRules::default(), &[(101, 10)], &[(100, 4)]);
+    assert_eq!(uncrossed_at(&events, 101), 4);
+    let events = place(&mut engine, limit(10, 9, OrderSide::Sell, 101, 6));
+    assert_eq!(trades(&events).len(), 1);
+    assert_eq!(trades(&events)[0].amount, 6);
+}
@@ -0,0 +1,90 @@
+mod support;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use match_engine::{journal::Journal, model::TradingStatus};
+
+use support::set_status;
+
+// A journal file holding two records, with the byte offset where the second
+// one starts.
+fn journal(name: &str) -> (PathBuf, usize) {
+    let path = std::env::temp_dir().join(format!("journal-{}-{name}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let (mut journal, entries) = Journal::open(path.to_str().unwrap()).unwrap();
+    assert!(entries.is_empty());
+    journal
+        .append(0, 1, 0, &set_status(TradingStatus::Auction))
+        .unwrap();
+    let second = fs::metadata(&path).unwrap().len() as usize;
+    journal
+        .append(0, 2, 0, &set_status(TradingStatus::Continuous))
+        .unwrap();
+    (path, second)
+}
+
+fn open(path: &Path) -> anyhow::Result<Vec<u64>> {
+    let (_, entries) = Journal::open(path.to_str().unwrap())?;
+    Ok(entries.iter().map(|entry| entry.sequence).collect())
+}
+
+fn corrupt(path: &Path, position: usize) {
+    let mut raw = fs::read(path).unwrap();
+    raw[position] ^= 0x40;
+    fs::write(path, raw).unwrap();
+}
+
+#[test]
+fn torn_tail_truncated() {
+    // Cut inside the second record's payload, then inside its header.
+    for (name, cut) in [("payload", 20), ("header", 5)] {
+        let (path, second) = journal(&format!("torn-{name}"));
+        let raw = fs::read(&path).unwrap();
+        fs::write(&path, &raw[..second + cut]).unwrap();
+
+        assert_eq!(open(&path).unwrap(), [0]);
+        assert_eq!(fs::metadata(&path).unwrap().len() as usize, second);
+        let (mut journal, _) = Journal::open(path.to_str().unwrap()).unwrap();
+        let sequence = journal.append(0, 2, 0, &set_status(TradingStatus::Continuous));
+        assert_eq!(sequence.unwrap(), 1);
+        assert_eq!(open(&path).unwrap(), [0, 1]);
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+// A bad payload checksum in the last record is a torn write, but one in an
+// earlier record is corruption.
+#[test]
+fn corrupt_payload_rejected_before_tail() {
+    let (path, second) = journal("payload");
+    corrupt(&path, second - 1);
+    let len = fs::metadata(&path).unwrap().len();
+    assert!(open(&path).is_err());
+    assert_eq!(fs::metadata(&path).unwrap().len(), len);
+    fs::remove_file(&path).unwrap();
+
+    let (path, second) = journal("payload-tail");
+    let len = fs::metadata(&path).unwrap().len() as usize;
+    corrupt(&path, len - 1);
+    assert_eq!(open(&path).unwrap(), [0]);
+    assert_eq!(fs::metadata(&path).unwrap().len() as usize, second);
+    fs::remove_file(&path).unwrap();
+}
+
+// A corrupt length can't be trusted to mark the tail, in any record, so the
+// journal is left untouched.
+#[test]
+fn corrupt_length_rejected() {
+    for record in 0..2 {
+        let (path, second) = journal(&format!("length-{record}"));
+        // The high byte, so the length reaches past the end of the file.
+        corrupt(&path, [0, second][record] + 3);
+        let len = fs::metadata(&path).unwrap().len();
+        assert!(open(&path).is_err());
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        fs::remove_file(&path).unwrap();
+    }
+}
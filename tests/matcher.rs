@@ -0,0 +1,150 @@
+use match_engine::{
+    matcher::Matcher,
+    model::{
+        Amount, BookOrder, EngineEvent, OrderId, OrderSide, Price, ProcessOrder, SelfTradeAction,
+        TimeInForce,
+    },
+    storage::BookSide,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use uuid::Uuid;
+
+fn order(
+    order_id: OrderId,
+    user: u128,
+    side: OrderSide,
+    price: i64,
+    amount: Amount,
+) -> ProcessOrder {
+    ProcessOrder {
+        order_id,
+        user_id: Uuid::from_u128(user),
+        side,
+        amount,
+        price: Price::from(price),
+        post_only: false,
+        is_market: false,
+        tif: TimeInForce::FOK,
+        display_amount: None,
+        self_trade_action: None,
+        market_to_limit: false,
+        min_qty: None,
+        all_or_none: false,
+    }
+}
+
+fn rest(book: &mut BookSide<Price>, order: ProcessOrder) {
+    book.insert(BookOrder::from(&order));
+}
+
+fn matched(
+    mut aggressor: ProcessOrder,
+    book: &mut BookSide<Price>,
+    self_trade_action: SelfTradeAction,
+) -> Amount {
+    Matcher::hard_match(&mut aggressor, book, &mut 0, self_trade_action, None)
+        .iter()
+        .map(|event| match event {
+            EngineEvent::TradeExecuted(trade) => trade.amount,
+            _ => 0,
+        })
+        .sum()
+}
+
+// The iceberg's first tranche leaves enough of the buy for the all-or-none
+// ask queued behind it, and the refreshed tranches cover the rest.
+#[test]
+fn fok_reaches_all_or_none_behind_iceberg() {
+    let mut asks = BookSide::new();
+    rest(
+        &mut asks,
+        ProcessOrder {
+            display_amount: Some(5),
+            ..order(1, 1, OrderSide::Sell, 100, 20)
+        },
+    );
+    rest(
+        &mut asks,
+        ProcessOrder {
+            all_or_none: true,
+            tif: TimeInForce::GTC,
+            ..order(2, 1, OrderSide::Sell, 100, 10)
+        },
+    );
+    let buy = order(3, 2, OrderSide::Buy, 100, 25);
+
+    let fillable = Matcher::fillable_amount(&buy, &asks, SelfTradeAction::Skip, None);
+    assert_eq!(fillable, 25);
+    assert_eq!(matched(buy, &mut asks, SelfTradeAction::Skip), 25);
+}
+
+// An all-or-none ask too large for the buy is passed over before self-trade
+// prevention looks at it, so the buyer's own one doesn't cancel the buy.
+#[test]
+fn own_all_or_none_skipped_before_self_trade_check() {
+    let mut asks = BookSide::new();
+    rest(
+        &mut asks,
+        ProcessOrder {
+            all_or_none: true,
+            tif: TimeInForce::GTC,
+            ..order(1, 2, OrderSide::Sell, 100, 50)
+        },
+    );
+    rest(&mut asks, order(2, 1, OrderSide::Sell, 100, 10));
+    let buy = order(3, 2, OrderSide::Buy, 100, 10);
+
+    for self_trade_action in [SelfTradeAction::CancelTaker, SelfTradeAction::CancelBoth] {
+        let fillable = Matcher::fillable_amount(&buy, &asks, self_trade_action, None);
+        assert_eq!(fillable, 10);
+    }
+    assert_eq!(matched(buy, &mut asks, SelfTradeAction::CancelTaker), 10);
+}
+
+// The dry run has to agree with the real match on any book, not just
+// conservatively: fill-or-kill relies on it in both directions.
+#[test]
+fn fillable_amount_matches_hard_match() {
+    let mut rng = StdRng::seed_from_u64(7);
+    for self_trade_action in [
+        SelfTradeAction::Allow,
+        SelfTradeAction::Skip,
+        SelfTradeAction::CancelMaker,
+        SelfTradeAction::CancelTaker,
+        SelfTradeAction::CancelBoth,
+    ] {
+        for _ in 0..500 {
+            let makers: Vec<ProcessOrder> = (0..rng.random_range(1..12))
+                .map(|order_id| {
+                    let amount = rng.random_range(1..=20);
+                    let all_or_none = rng.random_bool(0.25);
+                    ProcessOrder {
+                        display_amount: (!all_or_none && rng.random_bool(0.3))
+                            .then(|| rng.random_range(1..=amount)),
+                        all_or_none,
+                        tif: TimeInForce::GTC,
+                        ..order(
+                            order_id,
+                            rng.random_range(1..=3),
+                            OrderSide::Sell,
+                            rng.random_range(100..=103),
+                            amount,
+                        )
+                    }
+                })
+                .collect();
+            let buy = order(
+                100,
+                1,
+                OrderSide::Buy,
+                rng.random_range(100..=103),
+                rng.random_range(1..=60),
+            );
+
+            let mut asks = BookSide::new();
+            makers.into_iter().for_each(|maker| rest(&mut asks, maker));
+            let fillable = Matcher::fillable_amount(&buy, &asks, self_trade_action, None);
+            assert_eq!(fillable, matched(buy, &mut asks, self_trade_action));
+        }
+    }
+}
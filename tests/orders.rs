@@ -0,0 +1,153 @@
+mod support;
+
+use match_engine::{
+    config::TradingRules,
+    engine::MatchEngine,
+    model::{
+        EngineEvent, IncomingOrder, MarketDataMessage, OrderSide, OrderType, Price, PriceLevel,
+        RejectReason, SelfTradeAction,
+    },
+};
+
+use support::{amend, engine, limit, place, rejection, submit, trades};
+
+fn all_or_none(order: IncomingOrder) -> IncomingOrder {
+    let OrderType::Limit {
+        post_only,
+        price,
+        tif,
+        display_amount,
+        min_qty,
+        ..
+    } = order.order_type
+    else {
+        unreachable!("a limit order");
+    };
+    IncomingOrder {
+        order_type: OrderType::Limit {
+            post_only,
+            price,
+            tif,
+            display_amount,
+            min_qty,
+            all_or_none: true,
+        },
+        ..order
+    }
+}
+
+fn best_levels(engine: &MatchEngine) -> (Option<Price>, Option<Price>) {
+    let MarketDataMessage::DepthSnapshot { bids, asks, .. } = engine.depth_snapshot(1) else {
+        unreachable!("a depth snapshot");
+    };
+    let best = |levels: Vec<PriceLevel>| levels.first().map(|level| level.price);
+    (best(bids), best(asks))
+}
+
+// An all-or-none bid too large for the asks rests over them without showing
+// a crossed book, and still trades once an ask can fill it whole.
+#[test]
+fn resting_all_or_none_never_crosses_book() {
+    let mut engine = engine(TradingRules::default());
+    place(&mut engine, limit(1, 1, OrderSide::Sell, 9, 50));
+    let events = place(
+        &mut engine,
+        all_or_none(limit(2, 2, OrderSide::Buy, 10, 100)),
+    );
+    assert!(trades(&events).is_empty());
+    assert_eq!(best_levels(&engine), (None, Some(Price::from(9))));
+
+    let events = place(&mut engine, limit(3, 3, OrderSide::Sell, 10, 30));
+    assert!(trades(&events).is_empty());
+    assert_eq!(best_levels(&engine), (None, Some(Price::from(9))));
+
+    let events = place(&mut engine, limit(4, 4, OrderSide::Sell, 10, 100));
+    let trades = trades(&events);
+    assert_eq!(trades.len(), 1);
+    assert_eq!(trades[0].maker_order_id, 2);
+    assert_eq!(trades[0].amount, 100);
+    assert_eq!(best_levels(&engine), (None, Some(Price::from(9))));
+}
+
+// The all-or-none bid is left out of the best price, but a post-only ask big
+// enough to fill it would still take liquidity.
+#[test]
+fn post_only_amend_onto_all_or_none_rejected() {
+    let mut engine = engine(TradingRules::default());
+    place(
+        &mut engine,
+        all_or_none(limit(1, 1, OrderSide::Buy, 10, 100)),
+    );
+    let ask = limit(2, 2, OrderSide::Sell, 11, 100);
+    let OrderType::Limit { price, tif, .. } = ask.order_type else {
+        unreachable!("a limit order");
+    };
+    place(
+        &mut engine,
+        IncomingOrder {
+            order_type: OrderType::Limit {
+                post_only: true,
+                price,
+                tif,
+                display_amount: None,
+                min_qty: None,
+                all_or_none: false,
+            },
+            ..ask
+        },
+    );
+
+    let events = submit(&mut engine, amend(2, 2, Some(10), None), 0);
+    assert!(trades(&events).is_empty());
+    assert!(matches!(
+        rejection(&events),
+        Some(RejectReason::PostOnlyViolation)
+    ));
+}
+
+// An amend that crosses is matched under the order's own self-trade setting,
+// not the instrument default.
+#[test]
+fn amend_keeps_self_trade_action() {
+    let mut engine = engine(TradingRules::default());
+    place(&mut engine, limit(1, 1, OrderSide::Sell, 101, 10));
+    place(
+        &mut engine,
+        IncomingOrder {
+            self_trade_action: Some(SelfTradeAction::Allow),
+            ..limit(2, 1, OrderSide::Buy, 99, 10)
+        },
+    );
+
+    let events = submit(&mut engine, amend(2, 1, Some(101), None), 0);
+    let trades = trades(&events);
+    assert_eq!(trades.len(), 1);
+    assert_eq!(trades[0].amount, 10);
+}
+
+// Partial fills can leave less than the minimum size resting; moving the
+// remainder is still allowed, but resizing it below the minimum isn't.
+#[test]
+fn price_amend_allowed_below_min_amount() {
+    let mut engine = engine(TradingRules {
+        min_amount: Some(6),
+        ..TradingRules::default()
+    });
+    place(&mut engine, limit(1, 1, OrderSide::Sell, 101, 10));
+    let events = place(&mut engine, limit(2, 2, OrderSide::Buy, 101, 6));
+    assert_eq!(trades(&events).len(), 1);
+
+    let events = submit(&mut engine, amend(1, 1, Some(102), None), 0);
+    assert!(rejection(&events).is_none());
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, EngineEvent::OrderAmended { new_amount: 4, .. }))
+    );
+
+    let events = submit(&mut engine, amend(1, 1, Some(103), Some(3)), 0);
+    assert!(matches!(
+        rejection(&events),
+        Some(RejectReason::InvalidAmount)
+    ));
+}
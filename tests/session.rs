@@ -0,0 +1,130 @@
+mod support;
+
+use match_engine::{
+    config::{CircuitBreaker, TradingRules},
+    model::{
+        Command, EngineEvent, IncomingOrder, OrderSide, OrderType, Price, RejectReason,
+        TimeInForce, TradingStatus,
+    },
+};
+
+use support::{SYMBOL, engine, limit, place, rejection, set_status, statuses, submit, trades};
+
+fn breaker_rules() -> TradingRules {
+    let breaker: CircuitBreaker =
+        serde_json::from_str(r#"{"threshold": 0.05, "window_ms": 60000}"#).unwrap();
+    TradingRules {
+        circuit_breaker: Some(breaker),
+        ..TradingRules::default()
+    }
+}
+
+#[test]
+fn closing_uncross_does_not_trip_breaker() {
+    let mut engine = engine(breaker_rules());
+    place(&mut engine, limit(1, 1, OrderSide::Sell, 100, 1));
+    place(&mut engine, limit(2, 2, OrderSide::Buy, 100, 1));
+    submit(&mut engine, set_status(TradingStatus::Auction), 0);
+    place(&mut engine, limit(3, 1, OrderSide::Sell, 110, 1));
+    place(&mut engine, limit(4, 2, OrderSide::Buy, 110, 1));
+
+    let events = submit(&mut engine, set_status(TradingStatus::Closed), 0);
+    assert_eq!(trades(&events).len(), 1);
+    assert_eq!(statuses(&events), [TradingStatus::Closed]);
+
+    let events = place(&mut engine, limit(5, 2, OrderSide::Buy, 110, 1));
+    assert!(matches!(
+        rejection(&events),
+        Some(RejectReason::MarketClosed)
+    ));
+}
+
+#[test]
+fn continuous_move_trips_breaker() {
+    let mut engine = engine(breaker_rules());
+    place(&mut engine, limit(1, 1, OrderSide::Sell, 100, 1));
+    place(&mut engine, limit(2, 2, OrderSide::Buy, 100, 1));
+    place(&mut engine, limit(3, 1, OrderSide::Sell, 110, 1));
+    let events = place(&mut engine, limit(4, 2, OrderSide::Buy, 110, 1));
+    assert_eq!(statuses(&events), [TradingStatus::Halted]);
+
+    let resume = Command::Resume {
+        symbol: SYMBOL.to_string(),
+    };
+    let events = submit(&mut engine, resume, 0);
+    assert_eq!(statuses(&events), [TradingStatus::Continuous]);
+}
+
+#[test]
+fn closing_uncross_does_not_trigger_stops() {
+    let mut engine = engine(TradingRules::default());
+    let stop = IncomingOrder {
+        order_type: OrderType::StopLimit {
+            stop_price: Price::from(105),
+            post_only: false,
+            price: Price::from(110),
+            tif: TimeInForce::GTC,
+        },
+        ..limit(1, 3, OrderSide::Buy, 110, 1)
+    };
+    place(&mut engine, stop);
+    submit(&mut engine, set_status(TradingStatus::Auction), 0);
+    place(&mut engine, limit(2, 1, OrderSide::Sell, 106, 1));
+    place(&mut engine, limit(3, 2, OrderSide::Buy, 106, 1));
+
+    let events = submit(&mut engine, set_status(TradingStatus::Closed), 0);
+    assert_eq!(trades(&events).len(), 1);
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, EngineEvent::StopTriggered { .. }))
+    );
+    assert!(rejection(&events).is_none());
+
+    let cancel = Command::CancelOrder {
+        symbol: SYMBOL.to_string(),
+        order_id: 1,
+        user_id: support::user(3),
+    };
+    let events = submit(&mut engine, cancel, 0);
+    assert!(matches!(
+        events[..],
+        [EngineEvent::OrderCancelled { order_id: 1, .. }]
+    ));
+}
+
+#[test]
+fn schedule_does_not_lift_halt() {
+    const HOUR: i64 = 3_600_000_000_000;
+    let rules = TradingRules {
+        schedule: serde_json::from_str(
+            r#"[
+                {"at": "08:00", "status": "PreOpen"},
+                {"at": "09:00", "status": "Continuous"},
+                {"at": "17:00", "status": "Closed"}
+            ]"#,
+        )
+        .unwrap(),
+        ..TradingRules::default()
+    };
+    let mut engine = engine(rules);
+    let tick = || Command::Tick {
+        symbol: SYMBOL.to_string(),
+    };
+    let halt = Command::Halt {
+        symbol: SYMBOL.to_string(),
+    };
+
+    let events = submit(&mut engine, tick(), 8 * HOUR + HOUR / 4);
+    assert_eq!(statuses(&events), [TradingStatus::PreOpen]);
+    let events = submit(&mut engine, halt, 8 * HOUR + HOUR / 2);
+    assert_eq!(statuses(&events), [TradingStatus::Halted]);
+    let events = submit(&mut engine, tick(), 9 * HOUR + HOUR / 4);
+    assert!(statuses(&events).is_empty());
+
+    // The close still applies, and the next session opens as scheduled.
+    let events = submit(&mut engine, tick(), 17 * HOUR + HOUR / 4);
+    assert_eq!(statuses(&events), [TradingStatus::Closed]);
+    let events = submit(&mut engine, tick(), 32 * HOUR + HOUR / 4);
+    assert_eq!(statuses(&events), [TradingStatus::PreOpen]);
+}
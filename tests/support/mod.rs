@@ -0,0 +1,115 @@
+#![allow(dead_code)]
+
+use match_engine::{
+    config::{InstrumentConfig, TradingRules},
+    engine::MatchEngine,
+    model::{
+        Amount, Command, EngineEvent, IncomingOrder, OrderId, OrderSide, OrderType, Price,
+        RejectReason, SelfTradeAction, TimeInForce, Timestamp, Trade, TradingStatus,
+    },
+};
+use uuid::Uuid;
+
+pub const SYMBOL: &str = "TEST";
+
+pub fn engine(rules: TradingRules) -> MatchEngine {
+    MatchEngine::new(&InstrumentConfig {
+        symbol: SYMBOL.to_string(),
+        self_trade_action: SelfTradeAction::default(),
+        rules,
+    })
+}
+
+pub fn user(id: u128) -> Uuid {
+    Uuid::from_u128(id)
+}
+
+// A GTC limit order; tests adjust the fields they care about.
+pub fn limit(
+    order_id: OrderId,
+    user_id: u128,
+    side: OrderSide,
+    price: i64,
+    amount: Amount,
+) -> IncomingOrder {
+    IncomingOrder {
+        symbol: SYMBOL.to_string(),
+        order_id,
+        user_id: user(user_id),
+        side,
+        amount,
+        order_type: OrderType::Limit {
+            post_only: false,
+            price: Price::from(price),
+            tif: TimeInForce::GTC,
+            display_amount: None,
+            min_qty: None,
+            all_or_none: false,
+        },
+        self_trade_action: None,
+    }
+}
+
+pub fn amend(
+    order_id: OrderId,
+    user_id: u128,
+    price: Option<i64>,
+    amount: Option<Amount>,
+) -> Command {
+    Command::AmendOrder {
+        symbol: SYMBOL.to_string(),
+        order_id,
+        user_id: user(user_id),
+        price: price.map(Price::from),
+        amount,
+    }
+}
+
+pub fn set_status(status: TradingStatus) -> Command {
+    Command::SetTradingStatus {
+        symbol: SYMBOL.to_string(),
+        status,
+    }
+}
+
+// Processes a command at ingress time `now` and returns its events, leaving
+// out execution reports.
+pub fn submit(engine: &mut MatchEngine, command: Command, now: Timestamp) -> Vec<EngineEvent> {
+    engine
+        .process(command, now, now)
+        .into_iter()
+        .map(|envelope| envelope.event)
+        .filter(|event| !matches!(event, EngineEvent::ExecutionReport(_)))
+        .collect()
+}
+
+pub fn place(engine: &mut MatchEngine, order: IncomingOrder) -> Vec<EngineEvent> {
+    submit(engine, Command::NewOrder(order), 0)
+}
+
+pub fn trades(events: &[EngineEvent]) -> Vec<&Trade> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            EngineEvent::TradeExecuted(trade) => Some(trade),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn rejection(events: &[EngineEvent]) -> Option<&RejectReason> {
+    events.iter().find_map(|event| match event {
+        EngineEvent::OrderRejected { reason, .. } => Some(reason),
+        _ => None,
+    })
+}
+
+pub fn statuses(events: &[EngineEvent]) -> Vec<TradingStatus> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            EngineEvent::TradingStatusChanged { status, .. } => Some(*status),
+            _ => None,
+        })
+        .collect()
+}
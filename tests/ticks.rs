@@ -0,0 +1,125 @@
+mod support;
+
+use match_engine::{
+    config::TradingRules,
+    engine::MatchEngine,
+    model::{
+        Amount, Command, EngineEvent, IncomingOrder, MarketDataMessage, OrderId, OrderSide,
+        OrderType, PRICE_SCALE, Price, RejectReason,
+    },
+};
+
+use support::{SYMBOL, amend, engine, limit, place, rejection, submit, trades, user};
+
+// Prices in hundredths, as they appear on the wire.
+fn price(hundredths: i64) -> Price {
+    Price::from_units(hundredths * PRICE_SCALE / 100)
+}
+
+fn ticked(hundredths: i64) -> MatchEngine {
+    engine(TradingRules {
+        tick_size: Some(price(hundredths)),
+        ..TradingRules::default()
+    })
+}
+
+fn limit_at(
+    order_id: OrderId,
+    user_id: u128,
+    side: OrderSide,
+    hundredths: i64,
+    amount: Amount,
+) -> IncomingOrder {
+    let order = limit(order_id, user_id, side, 0, amount);
+    let OrderType::Limit { tif, .. } = order.order_type else {
+        unreachable!("a limit order");
+    };
+    IncomingOrder {
+        order_type: OrderType::Limit {
+            post_only: false,
+            price: price(hundredths),
+            tif,
+            display_amount: None,
+            min_qty: None,
+            all_or_none: false,
+        },
+        ..order
+    }
+}
+
+#[test]
+fn off_tick_prices_rejected() {
+    let mut engine = ticked(50);
+    let events = place(&mut engine, limit_at(1, 1, OrderSide::Buy, 10_025, 10));
+    assert!(matches!(
+        rejection(&events),
+        Some(RejectReason::InvalidPrice)
+    ));
+
+    place(&mut engine, limit_at(2, 1, OrderSide::Buy, 10_050, 10));
+    let events = submit(&mut engine, amend(2, 1, None, Some(5)), 0);
+    assert!(rejection(&events).is_none());
+    let events = submit(&mut engine, amend(2, 1, Some(100), None), 0);
+    assert!(rejection(&events).is_none());
+    let off_tick = Command::AmendOrder {
+        symbol: SYMBOL.to_string(),
+        order_id: 2,
+        user_id: user(1),
+        price: Some(price(10_010)),
+        amount: None,
+    };
+    let events = submit(&mut engine, off_tick, 0);
+    assert!(matches!(
+        rejection(&events),
+        Some(RejectReason::InvalidPrice)
+    ));
+}
+
+// Trades, depth and execution reports leave the engine in wire units, and an
+// average price between two ticks is reported exactly.
+#[test]
+fn prices_leave_engine_in_wire_units() {
+    let mut engine = ticked(50);
+    place(&mut engine, limit_at(1, 1, OrderSide::Sell, 10_000, 1));
+    place(&mut engine, limit_at(2, 1, OrderSide::Sell, 10_050, 1));
+    place(&mut engine, limit_at(3, 1, OrderSide::Sell, 10_100, 1));
+    let MarketDataMessage::DepthSnapshot { asks, .. } = engine.depth_snapshot(1) else {
+        unreachable!("a depth snapshot");
+    };
+    assert_eq!(asks[0].price, price(10_000));
+
+    let events: Vec<EngineEvent> = engine
+        .process(
+            Command::NewOrder(limit_at(4, 2, OrderSide::Buy, 10_050, 2)),
+            0,
+            0,
+        )
+        .into_iter()
+        .map(|envelope| envelope.event)
+        .collect();
+    let prices: Vec<Price> = trades(&events).iter().map(|trade| trade.price).collect();
+    assert_eq!(prices, [price(10_000), price(10_050)]);
+    let average_price = events.iter().rev().find_map(|event| match event {
+        EngineEvent::ExecutionReport(report) if report.order_id == 4 => report.average_price,
+        _ => None,
+    });
+    assert_eq!(average_price, Some(price(10_025)));
+}
+
+// A snapshot holds wire prices, so it restores under any tick size its prices
+// are on, and is refused under one they are off.
+#[test]
+fn snapshot_restores_across_tick_sizes() {
+    let mut engine = ticked(50);
+    place(&mut engine, limit_at(1, 1, OrderSide::Sell, 10_050, 10));
+    let snapshot = engine.snapshot();
+    assert_eq!(snapshot.asks[0].price, price(10_050));
+
+    let mut finer = ticked(25);
+    finer.restore(snapshot.clone()).unwrap();
+    let events = place(&mut finer, limit_at(2, 2, OrderSide::Buy, 10_075, 10));
+    assert_eq!(trades(&events)[0].price, price(10_050));
+
+    let mut coarser = ticked(100);
+    assert!(coarser.restore(snapshot).is_err());
+}